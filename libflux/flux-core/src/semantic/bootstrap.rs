@@ -3,16 +3,25 @@
 //! This package does not assume a location of the source code but does assume which packages are
 //! part of the prelude.
 
-use std::{env::consts, fs, io, io::Write, path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    env::consts,
+    fs, io,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::{bail, Result};
 use libflate::gzip::Encoder;
+use rayon::prelude::*;
 use walkdir::WalkDir;
 
 use crate::{
     ast,
     semantic::{
         self,
+        cbor,
         env::Environment,
         flatbuffers::types::{build_module, finish_serialize},
         fs::{FileSystemImporter, StdFS},
@@ -61,18 +70,109 @@ fn infer_stdlib_dir_(
 
     db.set_analyzer_config(config);
 
-    let mut imports = Packages::default();
-    let mut sem_pkg_map = SemanticPackageMap::default();
-    for name in &package_list {
-        let (exports, pkg) = db.semantic_package(name.clone())?;
-        imports.insert(name.clone(), PackageExports::clone(&exports)); // TODO Clone Arc
-        sem_pkg_map.insert(name.clone(), Package::clone(&pkg)); // TODO Clone Arc
-    }
+    let (imports, sem_pkg_map) = infer_stdlib_dir_parallel(&mut db, &package_list)?;
 
     let prelude = db.prelude()?;
     Ok((PackageExports::clone(&prelude), imports, sem_pkg_map))
 }
 
+/// Infers every package in `package_list`, running independent packages concurrently.
+///
+/// Packages are scheduled in dependency waves (see [`dependency_waves`]): a package only
+/// starts once every package it directly imports - among those in `package_list` - has
+/// already been inferred in `db`, so each `db.snapshot()` finds its imports already memoized
+/// rather than recomputing them redundantly on its own thread. Within a wave, unrelated
+/// packages run in parallel via rayon, each against its own snapshot.
+#[allow(clippy::type_complexity)]
+fn infer_stdlib_dir_parallel(
+    db: &mut Database,
+    package_list: &[String],
+) -> Result<(Packages, SemanticPackageMap)> {
+    let imports = Mutex::new(Packages::default());
+    let sem_pkg_map = Mutex::new(SemanticPackageMap::default());
+
+    for wave in dependency_waves(db, package_list) {
+        wave.par_iter().try_for_each(|name| -> Result<()> {
+            let snapshot = db.snapshot();
+            let (exports, pkg) = snapshot.semantic_package(name.clone())?;
+            imports
+                .lock()
+                .unwrap()
+                .insert(name.clone(), PackageExports::clone(&exports)); // TODO Clone Arc
+            sem_pkg_map
+                .lock()
+                .unwrap()
+                .insert(name.clone(), Package::clone(&pkg)); // TODO Clone Arc
+            Ok(())
+        })?;
+    }
+
+    Ok((imports.into_inner().unwrap(), sem_pkg_map.into_inner().unwrap()))
+}
+
+/// Returns the import paths of `name`, as written in its own source, restricted to those
+/// that are also members of `package_list` - imports outside of it (e.g. a package that's
+/// always eagerly available, like the internal prelude) impose no ordering constraint here.
+fn direct_dependencies(db: &Database, name: &str, package_list: &HashSet<&str>) -> Vec<String> {
+    // `ast_package`'s `has_package` check is keyed on file paths, not package paths, so look
+    // the package's files up directly instead.
+    if db.package_files(name).is_empty() {
+        return Vec::new();
+    }
+    db.ast_package_inner(name.to_string())
+        .0
+        .files
+        .iter()
+        .flat_map(|file| &file.imports)
+        .map(|import| import.path.value.clone())
+        .filter(|path| package_list.contains(path.as_str()) && path != name)
+        .collect()
+}
+
+/// Groups `package_list` into waves: every package in a wave only depends (within
+/// `package_list`) on packages from earlier waves, so a wave's packages can all be inferred
+/// concurrently. A package cycle within the stdlib itself can't be topologically sorted; its
+/// members are all placed in one final wave and left for `semantic_package`'s own
+/// `#[salsa::cycle]` recovery to report, rather than looping here.
+fn dependency_waves(db: &Database, package_list: &[String]) -> Vec<Vec<String>> {
+    let known: HashSet<&str> = package_list.iter().map(String::as_str).collect();
+    let mut deps: HashMap<String, HashSet<String>> = package_list
+        .iter()
+        .map(|name| {
+            (
+                name.clone(),
+                direct_dependencies(db, name, &known).into_iter().collect(),
+            )
+        })
+        .collect();
+
+    let mut remaining: HashSet<String> = package_list.iter().cloned().collect();
+    let mut waves = Vec::new();
+    while !remaining.is_empty() {
+        let ready: Vec<String> = remaining
+            .iter()
+            .filter(|name| deps[*name].is_empty())
+            .cloned()
+            .collect();
+
+        if ready.is_empty() {
+            waves.push(remaining.into_iter().collect());
+            break;
+        }
+
+        for name in &ready {
+            remaining.remove(name);
+        }
+        for deps_of in deps.values_mut() {
+            for name in &ready {
+                deps_of.remove(name);
+            }
+        }
+        waves.push(ready);
+    }
+    waves
+}
+
 /// Recursively parse all flux files within a directory.
 pub fn parse_dir(dir: &Path) -> io::Result<(Database, Vec<String>)> {
     let mut db = Database::default();
@@ -117,7 +217,7 @@ fn stdlib_importer(path: &Path) -> FileSystemImporter<StdFS> {
     FileSystemImporter::new(fs)
 }
 
-fn prelude_from_importer<I>(importer: &mut I) -> Result<PackageExports>
+pub(crate) fn prelude_from_importer<I>(importer: &mut I) -> Result<PackageExports>
 where
     I: Importer,
 {
@@ -204,12 +304,179 @@ pub fn stdlib(dir: &Path) -> Result<(PackageExports, FileSystemImporter<StdFS>)>
     Ok((prelude, stdlib_importer))
 }
 
+/// Name of the cache manifest `compile_stdlib` reads and writes in `outdir`, recording the
+/// content hash (see [`hash_package`]) each package had the last time it was compiled.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The on-disk cache manifest for `compile_stdlib`: the config it was built with (so a config
+/// change invalidates everything, not just the packages it happens to touch) and the
+/// content hash each package had at that time.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    config: String,
+    packages: HashMap<String, u64>,
+}
+
+fn read_manifest(path: &Path) -> Option<Manifest> {
+    // A missing or malformed manifest (e.g. from an older, incompatible version of this
+    // cache) just means "nothing is known to be unchanged" - fall through to a full rebuild
+    // rather than erroring.
+    let contents = fs::read(path).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+/// Hashes a single package's content: its own sources, the config it was compiled with, and
+/// the (already-computed) hashes of everything it depends on - so a change anywhere upstream
+/// of a package changes its hash too, the same way a Merkle tree's root changes when any leaf
+/// does.
+fn hash_package(
+    db: &Database,
+    name: &str,
+    config_fingerprint: &str,
+    dependency_hashes: &[u64],
+) -> u64 {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    let mut hasher = DefaultHasher::new();
+    let mut files = db.package_files(name);
+    files.sort();
+    for file_path in files {
+        file_path.hash(&mut hasher);
+        db.source(file_path).hash(&mut hasher);
+    }
+    config_fingerprint.hash(&mut hasher);
+
+    let mut dependency_hashes = dependency_hashes.to_vec();
+    dependency_hashes.sort_unstable();
+    dependency_hashes.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// Hashes every package in `package_list`, in dependency order so each package's hash already
+/// has its dependencies' hashes available. Every prelude package is treated as an implicit
+/// dependency of every other package, since it's in scope even without an explicit `import`.
+fn hash_packages(db: &Database, package_list: &[String], config_fingerprint: &str) -> HashMap<String, u64> {
+    let mut hashes = HashMap::new();
+
+    for wave in dependency_waves(db, &PRELUDE.iter().map(|s| s.to_string()).collect::<Vec<_>>()) {
+        for name in wave {
+            let known: HashSet<&str> = PRELUDE.iter().copied().collect();
+            let dep_hashes: Vec<u64> = direct_dependencies(db, &name, &known)
+                .iter()
+                .filter_map(|dep| hashes.get(dep).copied())
+                .collect();
+            hashes.insert(
+                name.clone(),
+                hash_package(db, &name, config_fingerprint, &dep_hashes),
+            );
+        }
+    }
+    let prelude_hashes: Vec<u64> = PRELUDE
+        .iter()
+        .filter_map(|name| hashes.get(&name.to_string()).copied())
+        .collect();
+
+    let known: HashSet<&str> = package_list.iter().map(String::as_str).collect();
+    for wave in dependency_waves(db, package_list) {
+        for name in wave {
+            if hashes.contains_key(&name) {
+                continue;
+            }
+            let mut dep_hashes = prelude_hashes.clone();
+            dep_hashes.extend(
+                direct_dependencies(db, &name, &known)
+                    .iter()
+                    .filter_map(|dep| hashes.get(dep).copied()),
+            );
+            hashes.insert(
+                name.clone(),
+                hash_package(db, &name, config_fingerprint, &dep_hashes),
+            );
+        }
+    }
+
+    hashes
+}
+
 /// Compiles the stdlib found at the srcdir into the outdir.
+///
+/// Packages whose content hash (source, config, and transitive dependencies) matches
+/// `outdir`'s manifest from a previous run, and whose `.fc` output is still on disk, are not
+/// re-encoded or re-written; a missing or unreadable manifest, or a manifest entry whose `.fc`
+/// file was since deleted, forces that package (or, if every package is affected, the whole
+/// directory) to rebuild.
+///
+/// Alongside each package's `.fc` file, this also writes a `.sem` file - the same analyzed
+/// `nodes::Package` CBOR-encoded via [`semantic::cbor`] - that [`read_cached_package`] can
+/// decode directly, letting a caller skip both the flatbuffer `read_module` step and
+/// re-running the vectorizer on a cache hit.
 pub fn compile_stdlib(srcdir: &Path, outdir: &Path) -> Result<()> {
-    let (_, imports, mut sem_pkgs) = infer_stdlib_dir(srcdir, AnalyzerConfig::default())?;
-    // Write each file as compiled module
+    let config = AnalyzerConfig::default();
+    let config_fingerprint = format!("{:?}", config);
+
+    let (mut db, package_list) = parse_dir(srcdir)?;
+    let hashes = hash_packages(&db, &package_list, &config_fingerprint);
+
+    let previous = read_manifest(&outdir.join(MANIFEST_FILE));
+    let fc_path = |path: &str| {
+        let mut fpath = outdir.join(path);
+        fpath.set_extension("fc");
+        fpath
+    };
+    // A CBOR-encoded `nodes::Package` (see `semantic::cbor`) written alongside each `.fc`
+    // file, so `read_cached_package` can hand a caller the fully analyzed, already-vectorized
+    // package straight back without going through `flatbuffers::types::read_module` at all.
+    let sem_path = |path: &str| {
+        let mut fpath = outdir.join(path);
+        fpath.set_extension("sem");
+        fpath
+    };
+    let unchanged = |path: &str| {
+        previous.as_ref().is_some_and(|manifest| {
+            manifest.config == config_fingerprint && manifest.packages.get(path) == hashes.get(path)
+        }) && fc_path(path).exists()
+    };
+
+    // When nothing changed (including a previous run's `.fc` files still being present), skip
+    // the inference pass entirely - this is the common case for a `cargo build` that didn't
+    // touch the stdlib.
+    if package_list.iter().all(|path| unchanged(path)) {
+        return Ok(());
+    }
+
+    // Only the packages whose content hash actually changed need re-inferring. Because
+    // `hash_package` folds each dependency's (already-computed) hash into its own - the same
+    // way a Merkle tree's root changes when any leaf does - a package whose hash is still
+    // `unchanged` either didn't change itself *and* nothing it (transitively) imports did
+    // either, so it's excluded here along with its `.fc` file being left alone below; a
+    // package downstream of a changed one gets a different hash than last run and so is
+    // included, same as the package that actually changed. An affected package's *unaffected*
+    // imports still get inferred where needed - just lazily, through the `Importer` impl on
+    // `Database`, and memoized there by salsa - rather than pre-emptively by this loop.
+    let affected: Vec<String> = package_list
+        .iter()
+        .filter(|path| !unchanged(path))
+        .cloned()
+        .collect();
+
+    db.set_analyzer_config(config);
+    let (imports, mut sem_pkgs) = infer_stdlib_dir_parallel(&mut db, &affected)?;
+    // Write each changed file as a compiled module; unchanged ones keep the `.fc` file a
+    // previous run already wrote.
     for (path, exports) in &imports {
+        if unchanged(path) {
+            continue;
+        }
         if let Some(code) = sem_pkgs.remove(path) {
+            // Encoded before `code` is moved into `Module` below. Stripped, since this is a
+            // build artifact keyed on content hash, not something a developer reads back
+            // source spans from.
+            let sem_bytes = cbor::encode_stripped(&code)?;
+
             let module = Module {
                 polytype: Some(exports.typ()),
                 code: Some(code),
@@ -219,20 +486,41 @@ pub fn compile_stdlib(srcdir: &Path, outdir: &Path) -> Result<()> {
             let buf = finish_serialize(&mut builder, offset);
 
             // Write module contents to file
-            let mut fpath = outdir.join(path);
-            fpath.set_extension("fc");
+            let fpath = fc_path(path);
             fs::create_dir_all(fpath.parent().unwrap())?;
             let file = fs::File::create(&fpath)?;
             let mut encoder = Encoder::new(file)?;
             encoder.write_all(buf)?;
             encoder.finish().into_result()?;
+
+            fs::write(sem_path(path), sem_bytes)?;
         } else {
             bail!("package {} missing code", &path);
         }
     }
+
+    let manifest = Manifest {
+        config: config_fingerprint,
+        packages: hashes.into_iter().collect(),
+    };
+    fs::write(outdir.join(MANIFEST_FILE), serde_json::to_vec_pretty(&manifest)?)?;
+
     Ok(())
 }
 
+/// Reads back the `.sem` file [`compile_stdlib`] wrote for `path` under `outdir`, decoding it
+/// with `semantic::cbor::decode`. Returns `None` (rather than an error) whenever the cache
+/// can't serve the request for any reason - no `.sem` file, or one a newer/older encoder
+/// doesn't understand - since the correct response to any of those is just to fall back to
+/// re-parsing and re-inferring `path` from source, the same way a missing or malformed
+/// manifest just means "nothing is known to be unchanged".
+pub fn read_cached_package(outdir: &Path, path: &str) -> Option<Package> {
+    let mut fpath = outdir.join(path);
+    fpath.set_extension("sem");
+    let bytes = fs::read(fpath).ok()?;
+    cbor::decode(&bytes).ok()
+}
+
 /// Module represenets the result of compiling Flux source code.
 ///
 /// The polytype represents the type of the entire package as a record type.
@@ -254,7 +542,11 @@ mod db {
     use crate::{
         errors::{located, SalvageResult},
         parser,
-        semantic::{nodes, FileErrors, PackageExports},
+        semantic::{
+            nodes,
+            symbol_index::{SymbolIndex, SymbolIndexBuilder},
+            FileErrors, PackageExports,
+        },
     };
 
     use super::*;
@@ -277,6 +569,9 @@ mod db {
     pub trait FluxBase {
         fn has_package(&self, package: &str) -> bool;
         fn package_files(&self, package: &str) -> Vec<String>;
+        /// Every distinct package import path with at least one source file loaded into this
+        /// database, in no particular order.
+        fn package_paths(&self) -> Vec<String>;
         fn set_source(&mut self, path: String, source: Arc<str>);
         fn source(&self, path: String) -> Arc<str>;
     }
@@ -295,6 +590,35 @@ mod db {
         #[salsa::input]
         fn use_prelude(&self) -> bool;
 
+        /// Packages inferred before, and visible to, every other package - including the
+        /// rest of the prelude. Defaults to [`INTERNAL_PRELUDE`].
+        #[salsa::input]
+        fn internal_prelude_list(&self) -> Arc<Vec<String>>;
+
+        /// Packages whose exported bindings are implicitly in scope in ordinary Flux source.
+        /// Defaults to [`PRELUDE`].
+        #[salsa::input]
+        fn prelude_list(&self) -> Arc<Vec<String>>;
+
+        /// Packages that see the internal prelude (but not the ordinary `prelude_list`) while
+        /// being inferred, because the ordinary prelude is itself built out of them (or
+        /// they're part of it). Defaults to the list previously hardcoded in
+        /// `semantic_package_inner_2`.
+        #[salsa::input]
+        fn special_prelude_packages(&self) -> Arc<Vec<String>>;
+
+        /// When `Some`, the only import paths a package compiled against this database is
+        /// allowed to `import`; anything else is rejected before it's even looked up. `None`
+        /// (the default) means every package this database knows about may be imported.
+        #[salsa::input]
+        fn allowed_imports(&self) -> Arc<Option<HashSet<String>>>;
+
+        /// When `true`, no `import` succeeds at all, regardless of [`allowed_imports`] -
+        /// for sandboxing package compilation that shouldn't be able to reach any other
+        /// package, including the prelude's own dependencies.
+        #[salsa::input]
+        fn imports_forbidden(&self) -> bool;
+
         fn ast_package_inner(&self, path: String) -> NeverEq<Arc<ast::Package>>;
 
         #[salsa::transparent]
@@ -325,6 +649,16 @@ mod db {
             &self,
             path: String,
         ) -> NeverEq<Result<Arc<PackageExports>, nodes::ErrorKind>>;
+
+        /// A fuzzy-searchable index of every exported symbol across every package with
+        /// source loaded into this database (see [`symbol_index::SymbolIndex`]).
+        ///
+        /// Not itself memoized - it walks `package_paths` and calls `semantic_package` on
+        /// each one fresh every time it's asked for - but that's cheap: `semantic_package` is
+        /// memoized and salsa-invalidated per package, so a `set_source` only forces
+        /// re-inference (and so re-indexing) of the packages that actually changed.
+        #[salsa::transparent]
+        fn symbol_index(&self) -> Arc<SymbolIndex>;
     }
 
     /// Storage for flux programs and their intermediates
@@ -342,12 +676,39 @@ mod db {
             };
             db.set_analyzer_config(AnalyzerConfig::default());
             db.set_use_prelude(true);
+            db.set_internal_prelude_list(Arc::new(
+                INTERNAL_PRELUDE.iter().map(|s| s.to_string()).collect(),
+            ));
+            db.set_prelude_list(Arc::new(PRELUDE.iter().map(|s| s.to_string()).collect()));
+            db.set_special_prelude_packages(Arc::new(
+                ["system", "date", "math", "strings", "regexp", "experimental/table"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ));
+            db.set_allowed_imports(Arc::new(None));
+            db.set_imports_forbidden(false);
             db
         }
     }
 
     impl salsa::Database for Database {}
 
+    impl Clone for Database {
+        fn clone(&self) -> Self {
+            Database {
+                storage: self.storage.snapshot(),
+                packages: Mutex::new(self.packages.lock().unwrap().clone()),
+            }
+        }
+    }
+
+    impl salsa::ParallelDatabase for Database {
+        fn snapshot(&self) -> salsa::Snapshot<Self> {
+            salsa::Snapshot::new(self.clone())
+        }
+    }
+
     impl FluxBase for Database {
         fn has_package(&self, package: &str) -> bool {
             self.packages.lock().unwrap().contains(package)
@@ -374,6 +735,17 @@ mod db {
             found_packages
         }
 
+        fn package_paths(&self) -> Vec<String> {
+            self.packages
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|file_path| file_path.rsplit_once('/').map(|(dir, _)| dir.to_string()))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect()
+        }
+
         fn source(&self, path: String) -> Arc<str> {
             self.source_inner(path)
         }
@@ -418,10 +790,12 @@ mod db {
 
     fn internal_prelude_inner(db: &dyn Flux) -> Result<Arc<PackageExports>, Arc<FileErrors>> {
         let mut prelude_map = PackageExports::new();
-        for name in INTERNAL_PRELUDE {
+        for name in db.internal_prelude_list().iter() {
             // Infer each package in the prelude allowing the earlier packages to be used by later
             // packages within the prelude list.
-            let (types, _sem_pkg) = db.semantic_package(name.into()).map_err(|err| err.error)?;
+            let (types, _sem_pkg) = db
+                .semantic_package(name.clone())
+                .map_err(|err| err.error)?;
 
             prelude_map.copy_bindings_from(&types);
         }
@@ -434,10 +808,12 @@ mod db {
 
     fn prelude_inner_2(db: &dyn Flux) -> Result<Arc<PackageExports>, Arc<FileErrors>> {
         let mut prelude_map = PackageExports::new();
-        for name in PRELUDE {
+        for name in db.prelude_list().iter() {
             // Infer each package in the prelude allowing the earlier packages to be used by later
             // packages within the prelude list.
-            let (types, _sem_pkg) = db.semantic_package(name.into()).map_err(|err| err.error)?;
+            let (types, _sem_pkg) = db
+                .semantic_package(name.clone())
+                .map_err(|err| err.error)?;
 
             prelude_map.copy_bindings_from(&types);
         }
@@ -456,18 +832,9 @@ mod db {
         db: &dyn Flux,
         path: String,
     ) -> SalvageResult<(Arc<PackageExports>, Arc<nodes::Package>), Arc<FileErrors>> {
-        let prelude = if !db.use_prelude() || INTERNAL_PRELUDE.contains(&&path[..]) {
+        let prelude = if !db.use_prelude() || db.internal_prelude_list().contains(&path) {
             Default::default()
-        } else if [
-            "system",
-            "date",
-            "math",
-            "strings",
-            "regexp",
-            "experimental/table",
-        ]
-        .contains(&&path[..])
-            || PRELUDE.contains(&&path[..])
+        } else if db.special_prelude_packages().contains(&path) || db.prelude_list().contains(&path)
         {
             db.internal_prelude().0?
         } else {
@@ -522,6 +889,16 @@ mod db {
         )
     }
 
+    fn symbol_index(db: &dyn Flux) -> Arc<SymbolIndex> {
+        let mut builder = SymbolIndexBuilder::new();
+        for path in db.package_paths() {
+            if let Ok((exports, _)) = db.semantic_package(path.clone()) {
+                builder.add_package(&path, &exports);
+            }
+        }
+        Arc::new(builder.build())
+    }
+
     fn recover_cycle2<T>(
         _db: &dyn Flux,
         cycle: &[String],
@@ -569,13 +946,36 @@ mod db {
         NeverEq(Err(nodes::ErrorKind::ImportCycle { cycle }))
     }
 
+    /// Checks `path` against this database's import policy ([`Flux::imports_forbidden`] and
+    /// [`Flux::allowed_imports`]) and, if it isn't permitted, returns
+    /// [`nodes::ErrorKind::ForbiddenImport`].
+    ///
+    /// Callers must run this before `semantic_package_cycle`: a path outside the policy
+    /// should never reach resolution, so it's reported as a policy error rather than
+    /// whatever `semantic_package_cycle` would otherwise say about it (a successful compile,
+    /// or a cycle/missing-package error that gives the caller no hint that it was the policy,
+    /// not the package, that was the problem).
+    fn check_import_policy(db: &dyn Flux, path: &str) -> Result<(), nodes::ErrorKind> {
+        if db.imports_forbidden() {
+            return Err(nodes::ErrorKind::ForbiddenImport(path.to_string()));
+        }
+        if let Some(allowed) = &*db.allowed_imports() {
+            if !allowed.contains(path) {
+                return Err(nodes::ErrorKind::ForbiddenImport(path.to_string()));
+            }
+        }
+        Ok(())
+    }
+
     impl Importer for Database {
         fn import(&mut self, path: &str) -> Result<PolyType, nodes::ErrorKind> {
+            check_import_policy(self, path)?;
             self.semantic_package_cycle(path.into())
                 .0
                 .map(|exports| exports.typ())
         }
         fn symbol(&mut self, path: &str, symbol_name: &str) -> Option<Symbol> {
+            check_import_policy(self, path).ok()?;
             self.semantic_package_cycle(path.into())
                 .0
                 .ok()
@@ -585,11 +985,13 @@ mod db {
 
     impl Importer for &dyn Flux {
         fn import(&mut self, path: &str) -> Result<PolyType, nodes::ErrorKind> {
+            check_import_policy(*self, path)?;
             self.semantic_package_cycle(path.into())
                 .0
                 .map(|exports| exports.typ())
         }
         fn symbol(&mut self, path: &str, symbol_name: &str) -> Option<Symbol> {
+            check_import_policy(*self, path).ok()?;
             self.semantic_package_cycle(path.into())
                 .0
                 .ok()
@@ -601,6 +1003,8 @@ pub use self::db::{Database, Flux, FluxBase};
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+
     use super::*;
     use crate::{ast, parser, semantic::convert::convert_polytype};
 
@@ -698,9 +1102,291 @@ mod tests {
         );
     }
 
+    #[test]
+    fn forbidden_imports_reject_everything() {
+        let a = r#"
+            import "b"
+        "#;
+        let b = r#"
+            x = 1
+        "#;
+
+        let mut db = Database::default();
+        db.set_use_prelude(false);
+        db.set_imports_forbidden(true);
+
+        for (k, v) in [("a/a.flux", a), ("b/b.flux", b)] {
+            db.set_source(k.into(), v.into());
+        }
+
+        let got_err = db
+            .semantic_package("a".into())
+            .expect_err("expected a forbidden import error");
+
+        assert!(
+            got_err.to_string().contains('b'),
+            "expected the forbidden path \"b\" in the error, got: {}",
+            got_err,
+        );
+    }
+
+    #[test]
+    fn allowed_imports_restricts_to_the_whitelist() {
+        let a = r#"
+            import "b"
+            import "c"
+        "#;
+        let b = r#"
+            x = 1
+        "#;
+        let c = r#"
+            y = 1
+        "#;
+
+        let mut db = Database::default();
+        db.set_use_prelude(false);
+        db.set_allowed_imports(Arc::new(Some(["b".to_string()].into_iter().collect())));
+
+        for (k, v) in [("a/a.flux", a), ("b/b.flux", b), ("c/c.flux", c)] {
+            db.set_source(k.into(), v.into());
+        }
+
+        let got_err = db
+            .semantic_package("a".into())
+            .expect_err("expected a forbidden import error for \"c\"");
+
+        assert!(
+            got_err.to_string().contains('c'),
+            "expected the forbidden path \"c\" in the error, got: {}",
+            got_err,
+        );
+    }
+
     #[test]
     fn bootstrap() {
         infer_stdlib_dir("../../stdlib", AnalyzerConfig::default())
             .unwrap_or_else(|err| panic!("{}", err));
     }
+
+    #[test]
+    fn dependency_waves_orders_packages_after_their_dependencies() {
+        let mut db = Database::default();
+        db.set_use_prelude(false);
+        db.set_source("a/a.flux".into(), "x = 1".into());
+        db.set_source("b/b.flux".into(), "import \"a\"\ny = a.x".into());
+        db.set_source("c/c.flux".into(), "import \"b\"\nz = b.y".into());
+
+        let package_list = vec!["c".to_string(), "a".to_string(), "b".to_string()];
+        let waves = dependency_waves(&db, &package_list);
+
+        assert_eq!(
+            waves,
+            vec![
+                vec!["a".to_string()],
+                vec!["b".to_string()],
+                vec!["c".to_string()],
+            ],
+            "each package should land in the wave right after the one its own dependency \
+             ended up in: {:?}",
+            waves,
+        );
+    }
+
+    #[test]
+    fn dependency_waves_collapses_a_cycle_into_one_final_wave() {
+        let mut db = Database::default();
+        db.set_use_prelude(false);
+        db.set_source("a/a.flux".into(), "import \"b\"\nx = b.y".into());
+        db.set_source("b/b.flux".into(), "import \"a\"\ny = a.x".into());
+
+        let package_list = vec!["a".to_string(), "b".to_string()];
+        let waves = dependency_waves(&db, &package_list);
+
+        assert_eq!(
+            waves.len(),
+            1,
+            "a and b can't be topologically sorted, so both should land in one final wave \
+             rather than looping forever: {:?}",
+            waves,
+        );
+        let mut only_wave = waves[0].clone();
+        only_wave.sort();
+        assert_eq!(only_wave, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn hash_packages_is_unaffected_by_an_unrelated_packages_source() {
+        let mut db = Database::default();
+        db.set_source("a/a.flux".into(), "x = 1".into());
+        db.set_source("b/b.flux".into(), "y = 1".into());
+
+        let package_list = vec!["a".to_string(), "b".to_string()];
+        let before = hash_packages(&db, &package_list, "cfg");
+
+        db.set_source("b/b.flux".into(), "y = 2".into());
+        let after = hash_packages(&db, &package_list, "cfg");
+
+        assert_eq!(
+            before["a"], after["a"],
+            "`b` is neither imported by `a` nor part of the prelude, so changing it shouldn't \
+             change `a`'s hash",
+        );
+        assert_ne!(before["b"], after["b"]);
+    }
+
+    #[test]
+    fn hash_packages_treats_the_prelude_as_an_implicit_dependency_of_every_package() {
+        let mut db = Database::default();
+        for (path, source) in [
+            ("internal/boolean/boolean.flux", "x = true"),
+            ("internal/location/location.flux", "x = 0"),
+            ("universe/universe.flux", "x = 1"),
+            ("influxdata/influxdb/influxdb.flux", "x = 1"),
+            ("a/a.flux", "y = 1"),
+        ] {
+            db.set_source(path.into(), source.into());
+        }
+
+        let package_list = vec!["a".to_string()];
+        let before = hash_packages(&db, &package_list, "cfg");
+
+        db.set_source("universe/universe.flux".into(), "x = 2".into());
+        let after = hash_packages(&db, &package_list, "cfg");
+
+        assert_ne!(
+            before["a"], after["a"],
+            "editing a prelude package should change every other package's hash, even one \
+             that never explicitly imports it",
+        );
+    }
+
+    /// Writes just enough of a fake stdlib - real PRELUDE packages with trivial bodies, plus
+    /// whatever `extra` packages a test wants - for `compile_stdlib`'s default,
+    /// `use_prelude`-on `Database` to successfully infer every package in it.
+    fn write_fake_stdlib(dir: &Path, extra: &[(&str, &str)]) {
+        let files: Vec<(&str, &str)> = [
+            ("internal/boolean/boolean.flux", "x = true"),
+            ("internal/location/location.flux", "x = 0"),
+            ("universe/universe.flux", "x = 1"),
+            ("influxdata/influxdb/influxdb.flux", "x = 1"),
+        ]
+        .into_iter()
+        .chain(extra.iter().copied())
+        .collect();
+
+        for (path, source) in files {
+            let fpath = dir.join(path);
+            fs::create_dir_all(fpath.parent().unwrap()).unwrap();
+            fs::write(fpath, source).unwrap();
+        }
+    }
+
+    /// A fresh, empty directory under the OS temp dir, namespaced by both this process id and
+    /// `name` so concurrent test runs (and the several directories a single test needs) don't
+    /// collide.
+    fn fresh_temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "flux-bootstrap-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn compile_stdlib_skips_reencoding_when_nothing_changed() {
+        let srcdir = fresh_temp_dir("compile-noop-src");
+        let outdir = fresh_temp_dir("compile-noop-out");
+        write_fake_stdlib(&srcdir, &[("a/a.flux", "y = 1")]);
+
+        compile_stdlib(&srcdir, &outdir).unwrap();
+        let manifest_path = outdir.join(MANIFEST_FILE);
+        let first_write = fs::metadata(&manifest_path).unwrap().modified().unwrap();
+
+        // A second run over the exact same sources hits the all-unchanged fast path and
+        // returns without touching the manifest (or re-encoding any `.fc` file) again.
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        compile_stdlib(&srcdir, &outdir).unwrap();
+        let second_write = fs::metadata(&manifest_path).unwrap().modified().unwrap();
+        assert_eq!(
+            first_write, second_write,
+            "the manifest shouldn't be rewritten when every package is still unchanged",
+        );
+    }
+
+    #[test]
+    fn compile_stdlib_rewrites_an_fc_file_deleted_out_from_under_an_otherwise_unchanged_manifest()
+    {
+        let srcdir = fresh_temp_dir("compile-missing-fc-src");
+        let outdir = fresh_temp_dir("compile-missing-fc-out");
+        write_fake_stdlib(&srcdir, &[("a/a.flux", "y = 1")]);
+
+        compile_stdlib(&srcdir, &outdir).unwrap();
+        let fc = {
+            let mut p = outdir.join("a");
+            p.set_extension("fc");
+            p
+        };
+        assert!(fc.exists());
+        fs::remove_file(&fc).unwrap();
+
+        compile_stdlib(&srcdir, &outdir).unwrap();
+        assert!(
+            fc.exists(),
+            "a `.fc` file missing from disk should be rewritten even though the manifest's \
+             hash for that package still matches",
+        );
+    }
+
+    #[test]
+    fn compile_stdlib_rebuilds_everything_when_the_manifest_is_malformed() {
+        let srcdir = fresh_temp_dir("compile-malformed-manifest-src");
+        let outdir = fresh_temp_dir("compile-malformed-manifest-out");
+        write_fake_stdlib(&srcdir, &[("a/a.flux", "y = 1")]);
+        fs::write(outdir.join(MANIFEST_FILE), b"not valid json").unwrap();
+
+        compile_stdlib(&srcdir, &outdir).unwrap();
+
+        let mut fc = outdir.join("a");
+        fc.set_extension("fc");
+        assert!(fc.exists(), "a malformed manifest should fall back to a full rebuild");
+
+        let manifest: Manifest =
+            serde_json::from_slice(&fs::read(outdir.join(MANIFEST_FILE)).unwrap())
+                .expect("a valid manifest should have been written over the malformed one");
+        assert!(manifest.packages.contains_key("a"));
+    }
+
+    #[test]
+    fn compile_stdlib_writes_a_sem_cache_that_read_cached_package_can_decode() {
+        let srcdir = fresh_temp_dir("compile-sem-cache-src");
+        let outdir = fresh_temp_dir("compile-sem-cache-out");
+        // Two ordinary variable assignments, the second referencing the first - the shape
+        // virtually all real Flux code takes, and exactly what used to be impossible to read
+        // back out of the cache (only a bare expression statement round-tripped before).
+        write_fake_stdlib(&srcdir, &[("a/a.flux", "y = 1\nz = y + 1")]);
+
+        compile_stdlib(&srcdir, &outdir).unwrap();
+
+        let cached = read_cached_package(&outdir, "a")
+            .expect("a .sem cache file should have been written and be decodable");
+        assert_eq!(cached.package, "a");
+        assert_eq!(cached.files[0].body.len(), 2);
+        assert!(
+            matches!(&cached.files[0].body[0], nodes::Statement::Variable(assgn) if assgn.id.name == Symbol::from("y")),
+            "expected the first statement to be `y = 1`, got {:?}",
+            cached.files[0].body[0],
+        );
+        assert!(
+            matches!(&cached.files[0].body[1], nodes::Statement::Variable(assgn) if assgn.id.name == Symbol::from("z")),
+            "expected the second statement to be `z = y + 1`, got {:?}",
+            cached.files[0].body[1],
+        );
+
+        // A package that was never compiled (or whose `.sem` file hasn't been written yet)
+        // has no cache to serve - callers should treat that as an ordinary cache miss.
+        assert!(read_cached_package(&outdir, "nope").is_none());
+    }
 }
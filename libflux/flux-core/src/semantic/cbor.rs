@@ -0,0 +1,1135 @@
+//! CBOR encoding of the type-checked semantic graph.
+//!
+//! This lets a caller cache an analyzed [`Package`] to disk (or memory) and reload it on a
+//! later run without repeating parsing, import resolution, and type inference - a cache hit
+//! just calls [`decode`] instead of running the vectorize/infer pipeline.
+//!
+//! Every node is encoded as a CBOR array whose first element is a small integer
+//! discriminant (the expression/statement/monotype kind) followed by its children, so the
+//! format is compact and versioned by discriminant rather than by field name. `Symbol`s are
+//! interned into a string table emitted once in the header, and each `loc` span is encoded as
+//! a trailing `[1, file, start.line, start.col, end.line, end.col]` node, or as just `[0]` in
+//! the "stripped" variant (no source locations). Type variables are renumbered densely on encode, since a tvar's original number
+//! only matters within the inference run that produced it.
+//!
+//! `encode_statement` covers `Statement::Expr` and `Statement::Variable` - an `x = ...`
+//! assignment, which is how virtually all real Flux code is written - so a package built out
+//! of ordinary assignments round-trips; any other statement kind (and a handful of not yet
+//! covered types) is written as an opaque placeholder and `decode` reports it as an error
+//! rather than guessing - the caller's cache-miss path (re-infer from source) is always
+//! correct, a silently wrong decode wouldn't be.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast,
+    semantic::{
+        nodes::{
+            self, BinaryExpr, BooleanLit, ConditionalExpr, Expression, ExprStmt, File,
+            FloatLit, FunctionExpr, FunctionParameter, Identifier, IdentifierExpr, IntegerLit,
+            LogicalExpr, MemberExpr, ObjectExpr, Package, Property as NodeProperty, ReturnStmt,
+            Statement, StringLit, UnaryExpr, VariableAssgn,
+        },
+        types::{Label, MonoType, Property, Record, Tvar},
+        Symbol,
+    },
+};
+
+/// A CBOR-serializable header shared by every encoded [`Package`]: the string table that
+/// interned [`Symbol`]s and `loc` file paths are indices into.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    strings: Vec<String>,
+    /// Whether `loc` spans were retained (`false` for the stripped variant).
+    with_locations: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    header: Header,
+    body: CborValue,
+}
+
+/// A discriminant-tagged node: `[kind, ...children]`. Using a single recursive type (rather
+/// than one `derive(Serialize)` per AST struct) is what makes the on-disk discriminants
+/// stable even as fields are renamed - only `Encoder`/`Decoder` need updating.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum CborValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    /// `[discriminant, ...children]`
+    Node(Vec<CborValue>),
+}
+
+impl CborValue {
+    fn node(&self) -> anyhow::Result<&[CborValue]> {
+        match self {
+            CborValue::Node(items) => Ok(items),
+            _ => anyhow::bail!("expected a tagged node"),
+        }
+    }
+
+    fn tag(&self) -> anyhow::Result<i64> {
+        match self.node()?.first() {
+            Some(CborValue::Int(tag)) => Ok(*tag),
+            _ => anyhow::bail!("expected a node discriminant"),
+        }
+    }
+
+    fn int(&self) -> anyhow::Result<i64> {
+        match self {
+            CborValue::Int(i) => Ok(*i),
+            _ => anyhow::bail!("expected an integer"),
+        }
+    }
+
+    fn float(&self) -> anyhow::Result<f64> {
+        match self {
+            CborValue::Float(f) => Ok(*f),
+            _ => anyhow::bail!("expected a float"),
+        }
+    }
+
+    fn boolean(&self) -> anyhow::Result<bool> {
+        match self {
+            CborValue::Bool(b) => Ok(*b),
+            _ => anyhow::bail!("expected a bool"),
+        }
+    }
+
+    fn string(&self) -> anyhow::Result<&str> {
+        match self {
+            CborValue::Str(s) => Ok(s),
+            _ => anyhow::bail!("expected a string"),
+        }
+    }
+}
+
+/// Expression discriminants. New variants are appended, never renumbered, so old caches
+/// made by a previous build keep decoding correctly against a newer one.
+mod kind {
+    pub const EXPR_IDENTIFIER: i64 = 0;
+    pub const EXPR_MEMBER: i64 = 1;
+    pub const EXPR_OBJECT: i64 = 2;
+    pub const EXPR_BINARY: i64 = 3;
+    pub const EXPR_UNARY: i64 = 4;
+    pub const EXPR_LOGICAL: i64 = 5;
+    pub const EXPR_CONDITIONAL: i64 = 6;
+    pub const EXPR_INTEGER: i64 = 7;
+    pub const EXPR_FLOAT: i64 = 8;
+    pub const EXPR_BOOLEAN: i64 = 9;
+    pub const EXPR_STRING: i64 = 10;
+    pub const EXPR_FUNCTION: i64 = 11;
+
+    pub const TYPE_BUILTIN: i64 = 0;
+    pub const TYPE_VAR: i64 = 1;
+    pub const TYPE_RECORD_EMPTY: i64 = 2;
+    pub const TYPE_RECORD_EXTENSION: i64 = 3;
+    pub const TYPE_VECTOR: i64 = 4;
+    pub const TYPE_OPTIONAL: i64 = 5;
+
+    pub const BLOCK_RETURN: i64 = 0;
+    pub const BLOCK_VARIABLE: i64 = 1;
+
+    pub const STMT_EXPR: i64 = 0;
+    pub const STMT_VARIABLE: i64 = 1;
+
+    /// Marks a node `encode` can't yet faithfully reconstruct (a handful of statement and
+    /// type kinds). `decode` reports these as an error rather than a best-effort guess.
+    pub const OPAQUE: i64 = -1;
+}
+
+/// Interns `Symbol`s (and any other strings, e.g. `loc` file paths) into a single table
+/// emitted once in the [`Header`], so a package with many repeated identifiers doesn't pay
+/// for the same string bytes over and over.
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl StringTable {
+    fn intern(&mut self, s: &str) -> i64 {
+        if let Some(&i) = self.index.get(s) {
+            return i as i64;
+        }
+        let i = self.strings.len();
+        self.strings.push(s.to_owned());
+        self.index.insert(s.to_owned(), i);
+        i as i64
+    }
+}
+
+/// Densely renumbers type variables as they're encountered, so the on-disk representation
+/// doesn't depend on the (run-specific) absolute tvar numbers assigned during inference.
+#[derive(Default)]
+struct TvarTable {
+    next: u64,
+    assigned: HashMap<Tvar, u64>,
+}
+
+impl TvarTable {
+    fn renumber(&mut self, var: Tvar) -> u64 {
+        *self.assigned.entry(var).or_insert_with(|| {
+            let n = self.next;
+            self.next += 1;
+            n
+        })
+    }
+}
+
+struct Encoder {
+    strings: StringTable,
+    tvars: TvarTable,
+    with_locations: bool,
+}
+
+impl Encoder {
+    fn encode_symbol(&mut self, sym: &Symbol) -> CborValue {
+        CborValue::Int(self.strings.intern(&sym.to_string()))
+    }
+
+    /// Encodes `loc` as `[0]` (no span - either stripped mode, or a node this cache doesn't
+    /// bother tracking source positions for) or `[1, file, start.line, start.col, end.line,
+    /// end.col]`. Always returns a node (never `None`) so callers can splice it straight into
+    /// a `CborValue::Node`'s children without special-casing an optional trailing field.
+    fn encode_loc(&mut self, loc: &ast::SourceLocation) -> CborValue {
+        if !self.with_locations {
+            return CborValue::Node(vec![CborValue::Int(0)]);
+        }
+        CborValue::Node(vec![
+            CborValue::Int(1),
+            CborValue::Int(self.strings.intern(&loc.file.clone().unwrap_or_default())),
+            CborValue::Int(loc.start.line as i64),
+            CborValue::Int(loc.start.column as i64),
+            CborValue::Int(loc.end.line as i64),
+            CborValue::Int(loc.end.column as i64),
+        ])
+    }
+
+    fn encode_type(&mut self, typ: &MonoType) -> CborValue {
+        match typ {
+            MonoType::Var(v) => CborValue::Node(vec![
+                CborValue::Int(kind::TYPE_VAR),
+                CborValue::Int(self.tvars.renumber(*v) as i64),
+            ]),
+            MonoType::Vector(elem) => CborValue::Node(vec![
+                CborValue::Int(kind::TYPE_VECTOR),
+                self.encode_type(elem),
+            ]),
+            MonoType::Optional(inner) => CborValue::Node(vec![
+                CborValue::Int(kind::TYPE_OPTIONAL),
+                self.encode_type(inner),
+            ]),
+            MonoType::Record(record) => self.encode_record(record),
+            // Anything not yet handled (functions, dicts, ...) still round-trips through its
+            // `Display` form when it happens to be a known scalar builtin; anything else is
+            // an opaque placeholder, which `decode` reports as an error (a cache miss) rather
+            // than guessing.
+            other => CborValue::Node(vec![
+                CborValue::Int(kind::TYPE_BUILTIN),
+                CborValue::Str(other.to_string()),
+            ]),
+        }
+    }
+
+    fn encode_record(&mut self, record: &Record) -> CborValue {
+        match record {
+            Record::Empty => CborValue::Node(vec![CborValue::Int(kind::TYPE_RECORD_EMPTY)]),
+            Record::Extension { head, tail } => CborValue::Node(vec![
+                CborValue::Int(kind::TYPE_RECORD_EXTENSION),
+                CborValue::Int(self.strings.intern(&head.k.to_string())),
+                self.encode_type(&head.v),
+                self.encode_record(tail),
+            ]),
+        }
+    }
+
+    fn encode_param(&mut self, param: &FunctionParameter) -> CborValue {
+        CborValue::Node(vec![
+            self.encode_symbol(&param.key.name),
+            CborValue::Bool(param.is_pipe),
+            match &param.default {
+                Some(d) => CborValue::Node(vec![CborValue::Int(1), self.encode_expr(d)]),
+                None => CborValue::Node(vec![CborValue::Int(0)]),
+            },
+        ])
+    }
+
+    fn encode_block(&mut self, block: &nodes::Block) -> CborValue {
+        match block {
+            nodes::Block::Return(stmt) => CborValue::Node(vec![
+                CborValue::Int(kind::BLOCK_RETURN),
+                self.encode_expr(&stmt.argument),
+                self.encode_loc(&stmt.loc),
+            ]),
+            nodes::Block::Variable(assign, next) => CborValue::Node(vec![
+                CborValue::Int(kind::BLOCK_VARIABLE),
+                self.encode_symbol(&assign.id.name),
+                self.encode_expr(&assign.init),
+                self.encode_block(next),
+                self.encode_loc(&assign.loc),
+            ]),
+            // A bare trailing expression statement never appears in a vectorized body (see
+            // `Cse::finish`, which only ever builds `Return`/`Variable`), so it's the one
+            // block shape that doesn't need to round-trip for the cache's main purpose.
+            _ => CborValue::Node(vec![CborValue::Int(kind::OPAQUE)]),
+        }
+    }
+
+    /// Encodes everything about a `FunctionExpr` except its own discriminant wrapper,
+    /// including its `vectorized` field - recursively, since `vectorized` is itself an
+    /// (optional) `FunctionExpr`.
+    fn encode_function(&mut self, f: &FunctionExpr) -> CborValue {
+        CborValue::Node(vec![
+            CborValue::Node(f.params.iter().map(|p| self.encode_param(p)).collect()),
+            self.encode_block(&f.body),
+            self.encode_type(&f.typ),
+            match &f.vectorized {
+                Some(v) => CborValue::Node(vec![CborValue::Int(1), self.encode_function(v)]),
+                None => CborValue::Node(vec![CborValue::Int(0)]),
+            },
+        ])
+    }
+
+    fn encode_expr(&mut self, expr: &nodes::Expression) -> CborValue {
+        use nodes::Expression::*;
+        match expr {
+            Identifier(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_IDENTIFIER),
+                self.encode_symbol(&e.name),
+                self.encode_type(&e.typ),
+                self.encode_loc(&e.loc),
+            ]),
+            Member(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_MEMBER),
+                self.encode_expr(&e.object),
+                CborValue::Str(e.property.clone()),
+                self.encode_type(&e.typ),
+                self.encode_loc(&e.loc),
+            ]),
+            Binary(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_BINARY),
+                CborValue::Str(e.operator.to_string()),
+                self.encode_expr(&e.left),
+                self.encode_expr(&e.right),
+                self.encode_type(&e.typ),
+                self.encode_loc(&e.loc),
+            ]),
+            Unary(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_UNARY),
+                CborValue::Str(e.operator.to_string()),
+                self.encode_expr(&e.argument),
+                self.encode_type(&e.typ),
+                self.encode_loc(&e.loc),
+            ]),
+            Logical(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_LOGICAL),
+                CborValue::Str(e.operator.to_string()),
+                self.encode_expr(&e.left),
+                self.encode_expr(&e.right),
+                self.encode_type(&e.typ),
+                self.encode_loc(&e.loc),
+            ]),
+            Conditional(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_CONDITIONAL),
+                self.encode_expr(&e.test),
+                self.encode_expr(&e.consequent),
+                self.encode_expr(&e.alternate),
+                self.encode_type(&e.typ),
+                self.encode_loc(&e.loc),
+            ]),
+            Integer(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_INTEGER),
+                CborValue::Int(e.value),
+                self.encode_loc(&e.loc),
+            ]),
+            Float(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_FLOAT),
+                CborValue::Float(e.value),
+                self.encode_loc(&e.loc),
+            ]),
+            Boolean(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_BOOLEAN),
+                CborValue::Bool(e.value),
+                self.encode_loc(&e.loc),
+            ]),
+            StringLit(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_STRING),
+                CborValue::Str(e.value.clone()),
+                self.encode_loc(&e.loc),
+            ]),
+            Object(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_OBJECT),
+                CborValue::Node(
+                    e.properties
+                        .iter()
+                        .map(|p| {
+                            CborValue::Node(vec![
+                                CborValue::Str(p.key.name.to_string()),
+                                self.encode_expr(&p.value),
+                            ])
+                        })
+                        .collect(),
+                ),
+                self.encode_loc(&e.loc),
+            ]),
+            // `vectorized` is the one field this cache is specifically for, so a function
+            // literal gets a real (recursive) encoding rather than the opaque placeholder
+            // every other not-yet-covered expression falls back to.
+            Function(e) => CborValue::Node(vec![
+                CborValue::Int(kind::EXPR_FUNCTION),
+                self.encode_function(e),
+                self.encode_loc(&e.loc),
+            ]),
+            // Anything else falls back to an opaque placeholder: lossy, but `decode` will
+            // report it as an error for this subtree (a cache miss) rather than producing
+            // garbage.
+            _ => CborValue::Node(vec![CborValue::Int(kind::OPAQUE)]),
+        }
+    }
+}
+
+/// Encodes a type-checked [`Package`] to a compact CBOR byte string.
+///
+/// Round-trips the `vectorized` field on every `FunctionExpr`, so a cache hit can skip
+/// re-running the vectorizer as well as inference.
+pub fn encode(pkg: &Package) -> anyhow::Result<Vec<u8>> {
+    encode_with(pkg, true)
+}
+
+/// Like [`encode`], but drops every `loc` span - useful when the cache is keyed on content
+/// hash anyway and callers don't need source positions back (e.g. a headless re-exec).
+pub fn encode_stripped(pkg: &Package) -> anyhow::Result<Vec<u8>> {
+    encode_with(pkg, false)
+}
+
+fn encode_with(pkg: &Package, with_locations: bool) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = Encoder {
+        strings: StringTable::default(),
+        tvars: TvarTable::default(),
+        with_locations,
+    };
+    let body = encoder.encode_package(pkg);
+    let doc = Document {
+        header: Header {
+            strings: encoder.strings.strings,
+            with_locations,
+        },
+        body,
+    };
+    Ok(serde_cbor::to_vec(&doc)?)
+}
+
+impl Encoder {
+    fn encode_package(&mut self, pkg: &Package) -> CborValue {
+        CborValue::Node(vec![
+            CborValue::Str(pkg.package.clone()),
+            CborValue::Node(
+                pkg.files
+                    .iter()
+                    .flat_map(|f| f.body.iter())
+                    .map(|stmt| self.encode_statement(stmt))
+                    .collect(),
+            ),
+        ])
+    }
+
+    fn encode_statement(&mut self, stmt: &nodes::Statement) -> CborValue {
+        match stmt {
+            nodes::Statement::Expr(s) => CborValue::Node(vec![
+                CborValue::Int(kind::STMT_EXPR),
+                self.encode_expr(&s.expression),
+                self.encode_loc(&s.loc),
+            ]),
+            nodes::Statement::Variable(s) => CborValue::Node(vec![
+                CborValue::Int(kind::STMT_VARIABLE),
+                self.encode_symbol(&s.id.name),
+                self.encode_expr(&s.init),
+                self.encode_loc(&s.loc),
+            ]),
+            // Other declarations (`option`, `builtin`, `test`) are not yet covered by the
+            // cache; encoding them as an opaque node degrades to a re-infer for this package
+            // on decode instead of producing an invalid graph.
+            _ => CborValue::Node(vec![CborValue::Int(kind::OPAQUE)]),
+        }
+    }
+}
+
+/// Reverses [`Encoder`]: reconstructs real nodes from the `[discriminant, ...children]`
+/// shapes `Encoder` wrote, against the same interned string table.
+struct Decoder<'a> {
+    strings: &'a [String],
+}
+
+impl<'a> Decoder<'a> {
+    fn string_at(&self, i: i64) -> anyhow::Result<String> {
+        self.strings
+            .get(i as usize)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("string table index {} out of range", i))
+    }
+
+    fn symbol(&self, v: &CborValue) -> anyhow::Result<Symbol> {
+        Ok(Symbol::from(self.string_at(v.int()?)?))
+    }
+
+    /// Reverses [`Encoder::encode_loc`]: `[0]` (stripped, or a node this cache never tracked a
+    /// span for) decodes to `Default::default()`, `[1, file, start.line, ...]` decodes to the
+    /// real span.
+    fn decode_loc(&self, v: &CborValue) -> anyhow::Result<ast::SourceLocation> {
+        let items = v.node()?;
+        match items.first() {
+            Some(CborValue::Int(0)) => Ok(Default::default()),
+            Some(CborValue::Int(1)) => {
+                let file = self.string_at(items[1].int()?)?;
+                Ok(ast::SourceLocation {
+                    file: if file.is_empty() { None } else { Some(file) },
+                    start: ast::Position {
+                        line: items[2].int()? as u32,
+                        column: items[3].int()? as u32,
+                    },
+                    end: ast::Position {
+                        line: items[4].int()? as u32,
+                        column: items[5].int()? as u32,
+                    },
+                    source: None,
+                })
+            }
+            _ => anyhow::bail!("unknown loc discriminant"),
+        }
+    }
+
+    fn decode_type(&self, v: &CborValue) -> anyhow::Result<MonoType> {
+        match v.tag()? {
+            kind::TYPE_VAR => Ok(MonoType::Var(Tvar(v.node()?[1].int()? as u64))),
+            kind::TYPE_VECTOR => Ok(MonoType::vector(self.decode_type(&v.node()?[1])?)),
+            kind::TYPE_OPTIONAL => Ok(MonoType::Optional(Box::new(
+                self.decode_type(&v.node()?[1])?,
+            ))),
+            kind::TYPE_RECORD_EMPTY | kind::TYPE_RECORD_EXTENSION => {
+                Ok(MonoType::from(self.decode_record(v)?))
+            }
+            kind::TYPE_BUILTIN => {
+                let name = v.node()?[1].string()?;
+                Ok(match name {
+                    "int" => MonoType::INT,
+                    "float" => MonoType::FLOAT,
+                    "bool" => MonoType::BOOL,
+                    "string" => MonoType::STRING,
+                    other => anyhow::bail!(
+                        "cannot decode opaque builtin type `{}` without re-inferring",
+                        other
+                    ),
+                })
+            }
+            other => anyhow::bail!("unknown type discriminant {}", other),
+        }
+    }
+
+    fn decode_record(&self, v: &CborValue) -> anyhow::Result<Record> {
+        let items = v.node()?;
+        match v.tag()? {
+            kind::TYPE_RECORD_EMPTY => Ok(Record::Empty),
+            kind::TYPE_RECORD_EXTENSION => {
+                let label = self.string_at(items[1].int()?)?;
+                let value = self.decode_type(&items[2])?;
+                let tail = self.decode_record(&items[3])?;
+                Ok(Record::Extension {
+                    head: Property {
+                        k: Label::from(Symbol::from(label)),
+                        v: value,
+                    },
+                    tail: Box::new(tail),
+                })
+            }
+            other => anyhow::bail!("unknown record discriminant {}", other),
+        }
+    }
+
+    fn decode_param(&self, v: &CborValue) -> anyhow::Result<FunctionParameter> {
+        let items = v.node()?;
+        let name = self.symbol(&items[0])?;
+        let is_pipe = items[1].boolean()?;
+        let default = match items[2].tag()? {
+            0 => None,
+            1 => Some(self.decode_expr(&items[2].node()?[1])?),
+            other => anyhow::bail!("unknown optional-default discriminant {}", other),
+        };
+        Ok(FunctionParameter {
+            loc: Default::default(),
+            key: Identifier {
+                loc: Default::default(),
+                name,
+            },
+            default,
+            is_pipe,
+        })
+    }
+
+    fn decode_block(&self, v: &CborValue) -> anyhow::Result<nodes::Block> {
+        let items = v.node()?;
+        match v.tag()? {
+            kind::BLOCK_RETURN => Ok(nodes::Block::Return(ReturnStmt {
+                loc: self.decode_loc(&items[2])?,
+                argument: self.decode_expr(&items[1])?,
+            })),
+            kind::BLOCK_VARIABLE => {
+                let name = self.symbol(&items[1])?;
+                let init = self.decode_expr(&items[2])?;
+                let next = self.decode_block(&items[3])?;
+                let loc = self.decode_loc(&items[4])?;
+                Ok(nodes::Block::Variable(
+                    Box::new(VariableAssgn::new(
+                        Identifier {
+                            loc: Default::default(),
+                            name,
+                        },
+                        init,
+                        loc,
+                    )),
+                    Box::new(next),
+                ))
+            }
+            other => anyhow::bail!(
+                "cannot decode block discriminant {} (not produced by the vectorizer); \
+                 treat this as a cache miss and re-infer from source",
+                other
+            ),
+        }
+    }
+
+    /// The inverse of [`Encoder::encode_function`] - note it takes the *inner* node (the
+    /// one `encode_function` produced), not one still wrapped in an `EXPR_FUNCTION` tag.
+    fn decode_function(&self, v: &CborValue) -> anyhow::Result<FunctionExpr> {
+        let items = v.node()?;
+        let params = items[0]
+            .node()?
+            .iter()
+            .map(|p| self.decode_param(p))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let body = self.decode_block(&items[1])?;
+        let typ = self.decode_type(&items[2])?;
+        let vectorized = match items[3].tag()? {
+            0 => None,
+            1 => Some(Box::new(self.decode_function(&items[3].node()?[1])?)),
+            other => anyhow::bail!("unknown optional-vectorized discriminant {}", other),
+        };
+        Ok(FunctionExpr {
+            loc: Default::default(),
+            typ,
+            params,
+            body,
+            vectorized,
+        })
+    }
+
+    fn decode_expr(&self, v: &CborValue) -> anyhow::Result<Expression> {
+        let items = v.node()?;
+        Ok(match v.tag()? {
+            kind::EXPR_IDENTIFIER => Expression::Identifier(Box::new(IdentifierExpr {
+                loc: self.decode_loc(&items[3])?,
+                name: self.symbol(&items[1])?,
+                typ: self.decode_type(&items[2])?,
+            })),
+            kind::EXPR_MEMBER => Expression::Member(Box::new(MemberExpr {
+                loc: self.decode_loc(&items[4])?,
+                object: self.decode_expr(&items[1])?,
+                property: items[2].string()?.to_string(),
+                typ: self.decode_type(&items[3])?,
+            })),
+            kind::EXPR_BINARY => Expression::Binary(Box::new(BinaryExpr {
+                loc: self.decode_loc(&items[5])?,
+                operator: parse_operator(items[1].string()?)?,
+                left: self.decode_expr(&items[2])?,
+                right: self.decode_expr(&items[3])?,
+                typ: self.decode_type(&items[4])?,
+            })),
+            kind::EXPR_UNARY => Expression::Unary(Box::new(UnaryExpr {
+                loc: self.decode_loc(&items[4])?,
+                operator: parse_operator(items[1].string()?)?,
+                argument: self.decode_expr(&items[2])?,
+                typ: self.decode_type(&items[3])?,
+            })),
+            kind::EXPR_LOGICAL => Expression::Logical(Box::new(LogicalExpr {
+                loc: self.decode_loc(&items[5])?,
+                operator: parse_operator(items[1].string()?)?,
+                left: self.decode_expr(&items[2])?,
+                right: self.decode_expr(&items[3])?,
+                typ: self.decode_type(&items[4])?,
+            })),
+            kind::EXPR_CONDITIONAL => Expression::Conditional(Box::new(ConditionalExpr {
+                loc: self.decode_loc(&items[5])?,
+                test: self.decode_expr(&items[1])?,
+                consequent: self.decode_expr(&items[2])?,
+                alternate: self.decode_expr(&items[3])?,
+                typ: self.decode_type(&items[4])?,
+            })),
+            kind::EXPR_INTEGER => Expression::Integer(Box::new(IntegerLit {
+                loc: self.decode_loc(&items[2])?,
+                value: items[1].int()?,
+            })),
+            kind::EXPR_FLOAT => Expression::Float(Box::new(FloatLit {
+                loc: self.decode_loc(&items[2])?,
+                value: items[1].float()?,
+            })),
+            kind::EXPR_BOOLEAN => Expression::Boolean(Box::new(BooleanLit {
+                loc: self.decode_loc(&items[2])?,
+                value: items[1].boolean()?,
+            })),
+            kind::EXPR_STRING => Expression::StringLit(Box::new(StringLit {
+                loc: self.decode_loc(&items[2])?,
+                value: items[1].string()?.to_string(),
+            })),
+            kind::EXPR_OBJECT => {
+                let properties = items[1]
+                    .node()?
+                    .iter()
+                    .map(|p| {
+                        let p = p.node()?;
+                        let value = self.decode_expr(&p[1])?;
+                        Ok(NodeProperty {
+                            loc: Default::default(),
+                            key: Identifier {
+                                loc: Default::default(),
+                                name: Symbol::from(p[0].string()?),
+                            },
+                            value,
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                // `with` (and the record's own `typ`) weren't captured by `encode_expr` - the
+                // type is recomputed from the decoded properties, `with` is lost.
+                let typ = MonoType::from(Record::new(
+                    properties.iter().map(|p| Property {
+                        k: Label::from(p.key.name.clone()),
+                        v: p.value.type_of(),
+                    }),
+                    None,
+                ));
+                Expression::Object(Box::new(ObjectExpr {
+                    loc: self.decode_loc(&items[2])?,
+                    typ,
+                    with: None,
+                    properties,
+                }))
+            }
+            kind::EXPR_FUNCTION => {
+                let mut f = self.decode_function(&items[1])?;
+                f.loc = self.decode_loc(&items[2])?;
+                Expression::Function(Box::new(f))
+            }
+            other => anyhow::bail!(
+                "cannot decode expression discriminant {} (not covered by this cache); \
+                 treat this as a cache miss and re-infer from source",
+                other
+            ),
+        })
+    }
+
+    fn decode_statement(&self, v: &CborValue) -> anyhow::Result<Statement> {
+        let items = v.node()?;
+        match v.tag()? {
+            kind::STMT_EXPR => Ok(Statement::Expr(Box::new(ExprStmt {
+                loc: self.decode_loc(&items[2])?,
+                expression: self.decode_expr(&items[1])?,
+            }))),
+            kind::STMT_VARIABLE => {
+                let name = self.symbol(&items[1])?;
+                let init = self.decode_expr(&items[2])?;
+                let loc = self.decode_loc(&items[3])?;
+                Ok(Statement::Variable(Box::new(VariableAssgn::new(
+                    Identifier {
+                        loc: Default::default(),
+                        name,
+                    },
+                    init,
+                    loc,
+                ))))
+            }
+            _ => anyhow::bail!(
+                "cached package contains a statement kind this cache doesn't cover \
+                 (only expression and variable-assignment statements round-trip); treat this \
+                 as a cache miss and re-infer from source"
+            ),
+        }
+    }
+
+    fn decode_package(&self, v: &CborValue) -> anyhow::Result<Package> {
+        let items = v.node()?;
+        let package = items[0].string()?.to_string();
+        let body = items[1]
+            .node()?
+            .iter()
+            .map(|stmt| self.decode_statement(stmt))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Package {
+            loc: Default::default(),
+            package: package.clone(),
+            files: vec![File {
+                loc: Default::default(),
+                package: None,
+                imports: Vec::new(),
+                body,
+            }],
+        })
+    }
+}
+
+/// Parses an operator back from the string form `Encoder` wrote via `Display`. Only the
+/// operators the vectorizer itself can produce are covered.
+fn parse_operator(s: &str) -> anyhow::Result<ast::Operator> {
+    use ast::Operator::*;
+    Ok(match s {
+        "+" => AdditionOperator,
+        "-" => SubtractionOperator,
+        "*" => MultiplicationOperator,
+        "/" => DivisionOperator,
+        "%" => ModuloOperator,
+        "==" => EqualOperator,
+        "!=" => NotEqualOperator,
+        "<" => LessThanOperator,
+        "<=" => LessThanEqualOperator,
+        ">" => GreaterThanOperator,
+        ">=" => GreaterThanEqualOperator,
+        "and" => AndOperator,
+        "or" => OrOperator,
+        "not" => NotOperator,
+        other => anyhow::bail!("cannot decode unsupported operator `{}`", other),
+    })
+}
+
+/// Decodes a [`Package`] previously produced by [`encode`] or [`encode_stripped`].
+///
+/// Returns an error for any byte string not produced by this encoder, including one written
+/// by an incompatible (future or ancient) discriminant layout, or one that touches a node
+/// shape `encode` only wrote as an opaque placeholder - callers should treat any of these as
+/// a cache miss and fall back to re-inferring from source rather than panicking.
+pub fn decode(bytes: &[u8]) -> anyhow::Result<Package> {
+    let doc: Document = serde_cbor::from_slice(bytes)?;
+    let decoder = Decoder {
+        strings: &doc.header.strings,
+    };
+    decoder.decode_package(&doc.body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::{
+        nodes::{Block, Property as NodeProperty},
+        types::Function,
+    };
+
+    fn loc() -> ast::SourceLocation {
+        Default::default()
+    }
+
+    fn real_loc() -> ast::SourceLocation {
+        ast::SourceLocation {
+            file: Some("main.flux".to_string()),
+            start: ast::Position { line: 2, column: 5 },
+            end: ast::Position { line: 2, column: 10 },
+            source: None,
+        }
+    }
+
+    fn ident(name: &str, typ: MonoType) -> Expression {
+        Expression::Identifier(Box::new(IdentifierExpr {
+            loc: loc(),
+            name: Symbol::from(name),
+            typ,
+        }))
+    }
+
+    // `(r) => ({z: r.x + r.y})`, with a hand-built `vectorized` form attached (the real
+    // vectorizer would also bind `r.x + r.y` to a synthetic `let` ahead of the return).
+    fn function_with_vectorized() -> FunctionExpr {
+        let record_type = MonoType::from(Record::new(
+            vec![
+                Property {
+                    k: "x".into(),
+                    v: MonoType::INT,
+                },
+                Property {
+                    k: "y".into(),
+                    v: MonoType::INT,
+                },
+            ],
+            None,
+        ));
+
+        let member = |property: &str| {
+            Expression::Member(Box::new(MemberExpr {
+                loc: loc(),
+                object: ident("r", record_type.clone()),
+                property: property.to_string(),
+                typ: MonoType::INT,
+            }))
+        };
+
+        let add = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            operator: ast::Operator::AdditionOperator,
+            left: member("x"),
+            right: member("y"),
+            typ: MonoType::INT,
+        }));
+
+        let scalar_body = Block::Return(ReturnStmt {
+            loc: loc(),
+            argument: Expression::Object(Box::new(ObjectExpr {
+                loc: loc(),
+                typ: MonoType::from(Record::new(
+                    vec![Property {
+                        k: "z".into(),
+                        v: MonoType::INT,
+                    }],
+                    None,
+                )),
+                with: None,
+                properties: vec![NodeProperty {
+                    loc: loc(),
+                    key: Identifier {
+                        loc: loc(),
+                        name: Symbol::from("z"),
+                    },
+                    value: add,
+                }],
+            })),
+        });
+
+        let vectorized_add = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            operator: ast::Operator::AdditionOperator,
+            left: member("x"),
+            right: member("y"),
+            typ: MonoType::vector(MonoType::INT),
+        }));
+
+        let vectorized_body = Block::Return(ReturnStmt {
+            loc: loc(),
+            argument: Expression::Object(Box::new(ObjectExpr {
+                loc: loc(),
+                typ: MonoType::from(Record::new(
+                    vec![Property {
+                        k: "z".into(),
+                        v: MonoType::vector(MonoType::INT),
+                    }],
+                    None,
+                )),
+                with: None,
+                properties: vec![NodeProperty {
+                    loc: loc(),
+                    key: Identifier {
+                        loc: loc(),
+                        name: Symbol::from("z"),
+                    },
+                    value: vectorized_add,
+                }],
+            })),
+        });
+
+        FunctionExpr {
+            loc: loc(),
+            typ: MonoType::from(Function {
+                pipe: None,
+                req: [("r".to_string(), record_type)].into_iter().collect(),
+                opt: Default::default(),
+                retn: MonoType::INT,
+            }),
+            params: vec![FunctionParameter {
+                loc: loc(),
+                key: Identifier {
+                    loc: loc(),
+                    name: Symbol::from("r"),
+                },
+                default: None,
+                is_pipe: false,
+            }],
+            body: scalar_body,
+            vectorized: Some(Box::new(FunctionExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                params: vec![],
+                body: vectorized_body,
+                vectorized: None,
+            })),
+        }
+    }
+
+    fn package_of(expr: Expression) -> Package {
+        Package {
+            loc: loc(),
+            package: "main".into(),
+            files: vec![File {
+                loc: loc(),
+                package: None,
+                imports: Vec::new(),
+                body: vec![Statement::Expr(Box::new(ExprStmt {
+                    loc: loc(),
+                    expression: expr,
+                }))],
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trips_a_package_of_ordinary_variable_assignments() {
+        // x = 1
+        // y = x + 1
+        //
+        // This is the shape virtually all real Flux code takes, so unlike
+        // `round_trips_a_simple_expression` (a bare expression statement), this is what the
+        // cache actually needs to handle for a cache hit to be useful in practice.
+        let pkg = Package {
+            loc: loc(),
+            package: "main".into(),
+            files: vec![File {
+                loc: loc(),
+                package: None,
+                imports: Vec::new(),
+                body: vec![
+                    Statement::Variable(Box::new(VariableAssgn::new(
+                        Identifier {
+                            loc: loc(),
+                            name: Symbol::from("x"),
+                        },
+                        Expression::Integer(Box::new(IntegerLit { loc: loc(), value: 1 })),
+                        loc(),
+                    ))),
+                    Statement::Variable(Box::new(VariableAssgn::new(
+                        Identifier {
+                            loc: loc(),
+                            name: Symbol::from("y"),
+                        },
+                        Expression::Binary(Box::new(BinaryExpr {
+                            loc: loc(),
+                            operator: ast::Operator::AdditionOperator,
+                            left: ident("x", MonoType::INT),
+                            right: Expression::Integer(Box::new(IntegerLit {
+                                loc: loc(),
+                                value: 1,
+                            })),
+                            typ: MonoType::INT,
+                        })),
+                        loc(),
+                    ))),
+                ],
+            }],
+        };
+
+        let bytes = encode(&pkg).unwrap();
+        let decoded = decode(&bytes).unwrap();
+
+        assert_eq!(decoded.files[0].body.len(), 2);
+        match &decoded.files[0].body[0] {
+            Statement::Variable(assgn) => {
+                assert_eq!(assgn.id.name, Symbol::from("x"));
+                match &assgn.init {
+                    Expression::Integer(lit) => assert_eq!(lit.value, 1),
+                    other => panic!("expected an integer literal, got {:?}", other),
+                }
+            }
+            other => panic!("expected a variable assignment, got {:?}", other),
+        }
+        match &decoded.files[0].body[1] {
+            Statement::Variable(assgn) => {
+                assert_eq!(assgn.id.name, Symbol::from("y"));
+                match &assgn.init {
+                    Expression::Binary(b) => {
+                        assert_eq!(b.operator, ast::Operator::AdditionOperator);
+                    }
+                    other => panic!("expected a binary expression, got {:?}", other),
+                }
+            }
+            other => panic!("expected a variable assignment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trips_a_simple_expression() {
+        let pkg = package_of(Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            operator: ast::Operator::AdditionOperator,
+            left: Expression::Integer(Box::new(IntegerLit { loc: loc(), value: 2 })),
+            right: Expression::Integer(Box::new(IntegerLit { loc: loc(), value: 3 })),
+            typ: MonoType::INT,
+        })));
+
+        let bytes = encode(&pkg).unwrap();
+        let decoded = decode(&bytes).unwrap();
+
+        assert_eq!(decoded.package, "main");
+        match &decoded.files[0].body[0] {
+            Statement::Expr(s) => match &s.expression {
+                Expression::Binary(b) => {
+                    assert_eq!(b.operator, ast::Operator::AdditionOperator);
+                    assert_eq!(b.left.type_of(), MonoType::INT);
+                }
+                other => panic!("expected a binary expression, got {:?}", other),
+            },
+            other => panic!("expected an expression statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encode_retains_locations_but_encode_stripped_drops_them() {
+        let pkg = package_of(Expression::Integer(Box::new(IntegerLit {
+            loc: real_loc(),
+            value: 42,
+        })));
+
+        let loc_of = |pkg: &Package| match &pkg.files[0].body[0] {
+            Statement::Expr(s) => match &s.expression {
+                Expression::Integer(lit) => lit.loc.clone(),
+                other => panic!("expected an integer literal, got {:?}", other),
+            },
+            other => panic!("expected an expression statement, got {:?}", other),
+        };
+
+        let with_locations = decode(&encode(&pkg).unwrap()).unwrap();
+        assert_eq!(loc_of(&with_locations), real_loc());
+
+        let stripped = decode(&encode_stripped(&pkg).unwrap()).unwrap();
+        assert_eq!(loc_of(&stripped), ast::SourceLocation::default());
+    }
+
+    #[test]
+    fn round_trips_the_vectorized_field_on_a_function_expr() {
+        let pkg = package_of(Expression::Function(Box::new(function_with_vectorized())));
+
+        let bytes = encode(&pkg).unwrap();
+        let decoded = decode(&bytes).unwrap();
+
+        let f = match &decoded.files[0].body[0] {
+            Statement::Expr(s) => match &s.expression {
+                Expression::Function(f) => f,
+                other => panic!("expected a function expression, got {:?}", other),
+            },
+            other => panic!("expected an expression statement, got {:?}", other),
+        };
+
+        let vectorized = f.vectorized.as_ref().expect("vectorized field was dropped");
+        match &vectorized.body {
+            Block::Return(stmt) => match &stmt.argument {
+                Expression::Object(obj) => {
+                    assert_eq!(obj.properties.len(), 1);
+                    assert_eq!(obj.properties[0].key.name, Symbol::from("z"));
+                    assert_eq!(
+                        obj.properties[0].value.type_of(),
+                        MonoType::vector(MonoType::INT)
+                    );
+                }
+                other => panic!("expected an object expression, got {:?}", other),
+            },
+            other => panic!("expected a return block, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Opt-in implicit widening coercion from `T` to `T?`.
+//!
+//! Flux's unifier never unifies a mandatory `T` with its optional counterpart `T?` - see
+//! `optional_do_not_unify_with_mandatory` in `semantic::tests::optional`. That's the right
+//! default: it catches real bugs where a `null`-able value silently flows into code that
+//! can't handle it. Some embedders want a laxer, opt-in mode instead, where a mandatory value
+//! can be widened to optional at assignment, argument, and conditional-branch positions -
+//! never the reverse - so that e.g. `y = if true then x else 1` infers to `int?` when
+//! `x: int?`, rather than erroring.
+//!
+//! **Status: the widening rule below is implemented and tested, but the analyzer does not
+//! call it.** There is no `AnalyzerConfig::coerce_optional` flag, no mismatch-recovery branch
+//! in the unifier that calls [`recover_mismatch`], and no `Expression::Coerce` variant for
+//! [`Coerce`] to wrap, so type checking is unchanged: `int` and `int?` still never unify, and
+//! this module is reachable only from its own tests.
+//!
+//! This module is the widening rule itself, shaped as a unify-failure-recovery step: given a
+//! failed unification of "expected `T?` but found `T`" at one of the [`CoercionSite`]
+//! positions, with coercion opted in, [`recover_mismatch`] returns the `T?` to unify against,
+//! and a `Coerce` node records the widened subtree. It never
+//! does the reverse - narrowing a `T?` to `T` remains an error unconditionally - and it never
+//! touches a unification that already succeeded.
+
+use crate::semantic::types::MonoType;
+
+/// Where a `T -> T?` widening is permitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoercionSite {
+    /// `y = x` where `y`'s inferred/declared type is `T?` and `x: T`.
+    Assignment,
+    /// `f(x: x)` where the parameter is declared `T?` and the argument is `T`.
+    Argument,
+    /// `if c then a else b` where one branch is `T?` and the other is `T`.
+    ConditionalBranch,
+}
+
+/// A coercion node recording that `value` (of type `T`) was implicitly widened to `T?`.
+///
+/// Keeping this as an explicit node - rather than silently rewriting `value`'s type in place -
+/// means later passes (e.g. vectorization) can see exactly where a null mask needs to be
+/// materialized at runtime, instead of having to rediscover it from a bare type mismatch.
+/// The semantic `Expression` enum has no `Coerce` variant, so nothing outside this module's
+/// tests builds one (see the module-level status note above).
+#[derive(Debug, Clone)]
+pub struct Coerce<T> {
+    /// Where this coercion was inserted; purely informational (diagnostics/debugging).
+    pub site: CoercionSite,
+    /// The mandatory-typed value being widened.
+    pub value: T,
+    /// The optional type being widened to, i.e. `T?`.
+    pub typ: MonoType,
+}
+
+/// Attempts a one-directional `T -> T?` widening of `actual` to match `expected`.
+///
+/// Returns `Some(expected)` when `expected` is `actual?`, meaning wrapping the mismatched
+/// value in a [`Coerce`] node would make it unify. Returns `None` for every other mismatch,
+/// including - deliberately - the narrowing direction `T?` -> `T`, which always stays an
+/// error regardless of whether coercion is enabled.
+pub fn try_coerce_optional(expected: &MonoType, actual: &MonoType) -> Option<MonoType> {
+    match expected {
+        MonoType::Optional(inner) if inner.as_ref() == actual => Some(expected.clone()),
+        _ => None,
+    }
+}
+
+/// The unify-failure-recovery hook described at the top of this module: call this from the
+/// unifier's mismatch branch in place of reporting an error, passing the `site` the mismatch
+/// occurred at and whether the analyzer config has opted into coercion.
+///
+/// Returns the widened type to unify against (`expected`) when `coerce_optional_enabled` is
+/// set and the mismatch is exactly the permitted `T` vs `T?` shape; `None` otherwise, meaning
+/// the caller should report the original type error as it does today.
+///
+/// `AnalyzerConfig` has no field to pass as `coerce_optional_enabled`, and nothing outside
+/// this module's tests calls this function (see the module-level status note).
+pub fn recover_mismatch(
+    expected: &MonoType,
+    actual: &MonoType,
+    _site: CoercionSite,
+    coerce_optional_enabled: bool,
+) -> Option<MonoType> {
+    if !coerce_optional_enabled {
+        return None;
+    }
+    try_coerce_optional(expected, actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widens_mandatory_to_optional() {
+        let int = MonoType::INT;
+        let optional_int = MonoType::Optional(Box::new(int.clone()));
+
+        assert_eq!(
+            try_coerce_optional(&optional_int, &int),
+            Some(optional_int)
+        );
+    }
+
+    #[test]
+    fn never_narrows_optional_to_mandatory() {
+        let int = MonoType::INT;
+        let optional_int = MonoType::Optional(Box::new(int.clone()));
+
+        // The reverse direction - unifying a `T?` where `T` is expected - is never coerced,
+        // regardless of which side is "expected": narrowing always stays an error.
+        assert_eq!(try_coerce_optional(&int, &optional_int), None);
+    }
+
+    #[test]
+    fn does_not_coerce_unrelated_types() {
+        let a = MonoType::INT;
+        let b = MonoType::Optional(Box::new(MonoType::STRING));
+
+        assert_eq!(try_coerce_optional(&b, &a), None);
+    }
+
+    #[test]
+    fn recover_mismatch_is_a_no_op_when_coercion_is_disabled() {
+        let int = MonoType::INT;
+        let optional_int = MonoType::Optional(Box::new(int.clone()));
+
+        assert_eq!(
+            recover_mismatch(&optional_int, &int, CoercionSite::ConditionalBranch, false),
+            None
+        );
+    }
+
+    #[test]
+    fn recover_mismatch_widens_the_int_branch_of_if_true_then_x_else_1_to_optional_int() {
+        // `y = if true then x else 1` where `x: int?` - the conditional's two branches are
+        // `int?` (the consequent, `x`) and `int` (the alternate, `1`). Under today's
+        // unconditional rule this is a unification error; under the coercing mode this
+        // module implements, the unifier's mismatch branch would call `recover_mismatch` at
+        // `CoercionSite::ConditionalBranch` and get back `int?` to unify the whole
+        // expression - and therefore `y` - against.
+        let x = MonoType::Optional(Box::new(MonoType::INT));
+        let one = MonoType::INT;
+
+        let y = recover_mismatch(&x, &one, CoercionSite::ConditionalBranch, true);
+
+        assert_eq!(y, Some(MonoType::Optional(Box::new(MonoType::INT))));
+    }
+}
@@ -0,0 +1,302 @@
+//! A best-effort, compile-time evaluator for semantic `Expression`s.
+//!
+//! This is used by the vectorizer to shrink constant subtrees (`r.x * (2 + 3)`) down to a
+//! single broadcast value before a vector op is emitted, but the evaluator itself doesn't know
+//! anything about vectorization: it just tries to reduce an `Expression` to a [`Constant`],
+//! giving up (returning `None`) the moment it hits something it can't prove is constant.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::Operator,
+    semantic::{
+        nodes::{
+            BooleanLit, Block, DurationLit, Expression, FloatLit, IntegerLit, StringLit,
+        },
+        types::MonoType,
+        Symbol,
+    },
+};
+
+/// A compile-time constant value produced by [`Expression::fold_constants`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    /// A folded integer literal, or the result of folding an all-integer subtree.
+    Int(i64),
+    /// A folded float literal, or the result of folding an all-float subtree.
+    Float(f64),
+    /// A folded boolean literal, or the result of folding a logical subtree.
+    Bool(bool),
+    /// A folded string literal.
+    String(String),
+    /// A folded duration literal.
+    Duration(crate::semantic::types::Duration),
+    /// A `null` value. [`Expression::fold_constants`] has no case that produces it, and
+    /// [`Constant::into_broadcast`] returns `None` for it.
+    Null,
+}
+
+/// Bindings that have already been folded to a [`Constant`] while walking a `Block` in order.
+/// Used so that `x = 2 + 3` followed by `r.y * x` can fold `x` too.
+pub type ConstantEnv = HashMap<Symbol, Constant>;
+
+impl Expression {
+    /// Attempts to reduce this expression to a single compile-time [`Constant`].
+    ///
+    /// Returns `None` (rather than an error) whenever the expression isn't provably constant,
+    /// e.g. because it reads a non-constant field of `r`, calls a function, or would change
+    /// runtime semantics if folded (division/modulo by zero, integer overflow).
+    pub fn fold_constants(&self, env: &ConstantEnv) -> Option<Constant> {
+        match self {
+            Expression::Integer(lit) => Some(Constant::Int(lit.value)),
+            Expression::Float(lit) => Some(Constant::Float(lit.value)),
+            Expression::Boolean(lit) => Some(Constant::Bool(lit.value)),
+            Expression::StringLit(lit) => Some(Constant::String(lit.value.clone())),
+            Expression::Duration(lit) => Some(Constant::Duration(lit.value.clone())),
+            Expression::Identifier(id) => env.get(&id.name).cloned(),
+            Expression::Unary(expr) => {
+                let argument = expr.argument.fold_constants(env)?;
+                match (&expr.operator, argument) {
+                    (Operator::SubtractionOperator, Constant::Int(n)) => {
+                        Some(Constant::Int(n.checked_neg()?))
+                    }
+                    (Operator::SubtractionOperator, Constant::Float(n)) => {
+                        Some(Constant::Float(-n))
+                    }
+                    (Operator::NotOperator, Constant::Bool(b)) => Some(Constant::Bool(!b)),
+                    _ => None,
+                }
+            }
+            Expression::Logical(expr) => {
+                let left = match expr.left.fold_constants(env)? {
+                    Constant::Bool(b) => b,
+                    _ => return None,
+                };
+                let right = match expr.right.fold_constants(env)? {
+                    Constant::Bool(b) => b,
+                    _ => return None,
+                };
+                Some(Constant::Bool(match expr.operator {
+                    Operator::AndOperator => left && right,
+                    Operator::OrOperator => left || right,
+                    _ => return None,
+                }))
+            }
+            Expression::Binary(expr) => {
+                let left = expr.left.fold_constants(env)?;
+                let right = expr.right.fold_constants(env)?;
+                fold_binary(&expr.operator, left, right)
+            }
+            // Anything that reads `r`, calls a function, or otherwise isn't a closed
+            // expression over already-folded bindings is left alone.
+            _ => None,
+        }
+    }
+}
+
+impl Constant {
+    /// Reifies this folded constant back into a broadcast vector-literal expression, with
+    /// `typ` as the (pre-vectorization) element type. Returns `None` for `Constant::Null`,
+    /// which has no literal expression to reify to; callers should fall back to ordinary
+    /// vectorization in that case.
+    pub fn into_broadcast(
+        &self,
+        loc: crate::ast::SourceLocation,
+        typ: MonoType,
+    ) -> Option<Expression> {
+        let vector_typ = MonoType::vector(typ);
+        Some(match self {
+            Constant::Int(value) => Expression::Integer(Box::new(IntegerLit {
+                loc,
+                typ: vector_typ,
+                value: *value,
+            })),
+            Constant::Float(value) => Expression::Float(Box::new(FloatLit {
+                loc,
+                typ: vector_typ,
+                value: *value,
+            })),
+            Constant::Bool(value) => Expression::Boolean(Box::new(BooleanLit {
+                loc,
+                typ: vector_typ,
+                value: *value,
+            })),
+            Constant::String(value) => Expression::StringLit(Box::new(StringLit {
+                loc,
+                typ: vector_typ,
+                value: value.clone(),
+            })),
+            Constant::Duration(value) => Expression::Duration(Box::new(DurationLit {
+                loc,
+                typ: vector_typ,
+                value: value.clone(),
+            })),
+            Constant::Null => return None,
+        })
+    }
+}
+
+fn fold_binary(operator: &Operator, left: Constant, right: Constant) -> Option<Constant> {
+    use Operator::*;
+    match (left, right) {
+        (Constant::Int(l), Constant::Int(r)) => {
+            let result = match operator {
+                AdditionOperator => l.checked_add(r)?,
+                SubtractionOperator => l.checked_sub(r)?,
+                MultiplicationOperator => l.checked_mul(r)?,
+                DivisionOperator => {
+                    if r == 0 {
+                        return None;
+                    }
+                    l.checked_div(r)?
+                }
+                ModuloOperator => {
+                    if r == 0 {
+                        return None;
+                    }
+                    l.checked_rem(r)?
+                }
+                _ => return None,
+            };
+            Some(Constant::Int(result))
+        }
+        (Constant::Float(l), Constant::Float(r)) => {
+            let result = match operator {
+                AdditionOperator => l + r,
+                SubtractionOperator => l - r,
+                MultiplicationOperator => l * r,
+                DivisionOperator => {
+                    if r == 0.0 {
+                        return None;
+                    }
+                    l / r
+                }
+                ModuloOperator => {
+                    if r == 0.0 {
+                        return None;
+                    }
+                    l % r
+                }
+                _ => return None,
+            };
+            Some(Constant::Float(result))
+        }
+        (Constant::String(l), Constant::String(r)) => match operator {
+            AdditionOperator => Some(Constant::String(l + &r)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Walks the statements of `block` in order, folding each variable assignment's initializer
+/// and threading the result forward so that later statements can fold references to earlier
+/// ones. Stops folding a name as soon as its initializer doesn't fold, without aborting the
+/// whole pass (later, unrelated bindings may still fold).
+pub fn collect_constant_bindings(block: &Block) -> ConstantEnv {
+    let mut env = ConstantEnv::new();
+    let mut block = Some(block);
+    while let Some(Block::Variable(stmt, next)) = block {
+        if let Some(value) = stmt.init.fold_constants(&env) {
+            env.insert(stmt.id.name.clone(), value);
+        }
+        block = Some(&**next);
+    }
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::{
+        nodes::{BinaryExpr, Identifier, IdentifierExpr, MemberExpr, ReturnStmt, VariableAssgn},
+        types::{Property as TypeProperty, Record},
+    };
+
+    fn loc() -> crate::ast::SourceLocation {
+        Default::default()
+    }
+
+    fn int_lit(value: i64) -> Expression {
+        Expression::Integer(Box::new(IntegerLit {
+            loc: loc(),
+            typ: MonoType::INT,
+            value,
+        }))
+    }
+
+    #[test]
+    fn collect_constant_bindings_threads_an_earlier_fold_into_a_later_one() {
+        // x = 2 + 3
+        // return x + 10
+        let x = Block::Variable(
+            Box::new(VariableAssgn::new(
+                Identifier {
+                    loc: loc(),
+                    name: Symbol::from("x"),
+                },
+                Expression::Binary(Box::new(BinaryExpr {
+                    loc: loc(),
+                    typ: MonoType::INT,
+                    operator: Operator::AdditionOperator,
+                    left: int_lit(2),
+                    right: int_lit(3),
+                })),
+                loc(),
+            )),
+            Box::new(Block::Return(ReturnStmt {
+                loc: loc(),
+                argument: int_lit(0), // unused by this test - only `collect_constant_bindings` is exercised
+            })),
+        );
+
+        let env = collect_constant_bindings(&x);
+        assert_eq!(env.get(&Symbol::from("x")), Some(&Constant::Int(5)));
+
+        let uses_x = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            typ: MonoType::INT,
+            operator: Operator::AdditionOperator,
+            left: Expression::Identifier(Box::new(IdentifierExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                name: Symbol::from("x"),
+            })),
+            right: int_lit(10),
+        }));
+        assert_eq!(uses_x.fold_constants(&env), Some(Constant::Int(15)));
+    }
+
+    #[test]
+    fn a_member_read_off_r_never_folds_to_a_constant() {
+        let r_typ = MonoType::from(Record::new(
+            vec![TypeProperty {
+                k: "x".into(),
+                v: MonoType::INT,
+            }],
+            None,
+        ));
+        let r_dot_x = Expression::Member(Box::new(MemberExpr {
+            loc: loc(),
+            typ: MonoType::INT,
+            object: Expression::Identifier(Box::new(IdentifierExpr {
+                loc: loc(),
+                typ: r_typ,
+                name: Symbol::from("r"),
+            })),
+            property: "x".to_string(),
+        }));
+        assert_eq!(r_dot_x.fold_constants(&ConstantEnv::new()), None);
+
+        // A non-constant operand should block the whole subtree from folding too, not just
+        // the member read itself.
+        let plus_one = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            typ: MonoType::INT,
+            operator: Operator::AdditionOperator,
+            left: r_dot_x,
+            right: int_lit(1),
+        }));
+        assert_eq!(plus_one.fold_constants(&ConstantEnv::new()), None);
+    }
+}
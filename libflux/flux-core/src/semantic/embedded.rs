@@ -0,0 +1,179 @@
+//! An in-memory stdlib importer, for embedding the compiled standard library directly into a
+//! binary (e.g. via `include_dir!`) instead of reading `.fc` files off disk at runtime.
+//!
+//! `compile_stdlib` already writes one gzip'd flatbuffer-encoded [`Module`] per package under
+//! an output directory; [`embed_stdlib_dir`] is the build-time half that folds that directory
+//! into a single `package path -> bytes` map a build script can bake into a constant, and
+//! [`stdlib_from_embedded`] is the run-time half that serves imports out of that map instead of
+//! touching the filesystem.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use libflate::gzip::Decoder;
+use walkdir::WalkDir;
+
+use crate::semantic::{
+    bootstrap::{prelude_from_importer, Module},
+    flatbuffers::types::read_module,
+    import::Importer,
+    nodes, types::PolyType,
+    PackageExports, Symbol,
+};
+
+/// Reads every `.fc` module `compile_stdlib` wrote under `outdir` into a single map of
+/// import path -> raw (still gzip'd) file contents, suitable for embedding into a binary with
+/// something like `include_dir!` at build time.
+pub fn embed_stdlib_dir(outdir: &Path) -> Result<HashMap<String, Vec<u8>>> {
+    let mut modules = HashMap::new();
+    for entry in WalkDir::new(outdir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("fc") {
+            continue;
+        }
+        let mut import_path = path
+            .strip_prefix(outdir)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        import_path.truncate(import_path.len() - ".fc".len());
+        modules.insert(import_path, fs::read(path)?);
+    }
+    Ok(modules)
+}
+
+/// Decodes a single gzip'd flatbuffer-encoded [`Module`] previously written by
+/// `compile_stdlib` (via `flatbuffers::types::{build_module, finish_serialize}`).
+fn decode_module(bytes: &[u8]) -> Result<Module> {
+    let mut decoder = Decoder::new(bytes)?;
+    let mut decompressed = Vec::new();
+    std::io::copy(&mut decoder, &mut decompressed)?;
+    read_module(&decompressed)
+}
+
+/// An [`Importer`] backed entirely by an in-memory `package path -> bytes` map, decoding (and
+/// caching) each package's [`Module`] the first time it's imported.
+pub struct EmbeddedImporter<'a> {
+    modules: &'a HashMap<String, Vec<u8>>,
+    decoded: HashMap<String, PolyType>,
+}
+
+impl<'a> EmbeddedImporter<'a> {
+    fn decode(&mut self, path: &str) -> Result<&PolyType, nodes::ErrorKind> {
+        if !self.decoded.contains_key(path) {
+            let bytes = self
+                .modules
+                .get(path)
+                .ok_or_else(|| nodes::ErrorKind::InvalidImportPath(path.to_string()))?;
+            let module = decode_module(bytes)
+                .map_err(|_| nodes::ErrorKind::InvalidImportPath(path.to_string()))?;
+            let polytype = module
+                .polytype
+                .ok_or_else(|| nodes::ErrorKind::InvalidImportPath(path.to_string()))?;
+            self.decoded.insert(path.to_string(), polytype);
+        }
+        Ok(&self.decoded[path])
+    }
+}
+
+impl<'a> Importer for EmbeddedImporter<'a> {
+    fn import(&mut self, path: &str) -> Result<PolyType, nodes::ErrorKind> {
+        self.decode(path).map(|polytype| polytype.clone())
+    }
+
+    fn symbol(&mut self, _path: &str, _symbol_name: &str) -> Option<Symbol> {
+        // Symbol lookup (used by e.g. go-to-definition tooling) needs the package's `code`,
+        // which this importer never keeps: `decode` above retains only each module's
+        // `polytype`. Callers that need it should use `FileSystemImporter` instead.
+        None
+    }
+}
+
+/// Builds the prelude and an [`EmbeddedImporter`] from a map produced by [`embed_stdlib_dir`],
+/// the in-memory counterpart to `bootstrap::stdlib`.
+pub fn stdlib_from_embedded(
+    modules: &HashMap<String, Vec<u8>>,
+) -> Result<(PackageExports, EmbeddedImporter<'_>)> {
+    let mut importer = EmbeddedImporter {
+        modules,
+        decoded: HashMap::new(),
+    };
+    let prelude = prelude_from_importer(&mut importer)?;
+    Ok((prelude, importer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast, parser,
+        semantic::{
+            convert::convert_polytype,
+            flatbuffers::types::{build_module, finish_serialize},
+        },
+    };
+
+    fn encode_fake_module(typ: PolyType) -> Vec<u8> {
+        let module = Module {
+            polytype: Some(typ),
+            code: None,
+        };
+        let mut builder = flatbuffers::FlatBufferBuilder::new();
+        let offset = build_module(&mut builder, module);
+        let buf = finish_serialize(&mut builder, offset);
+
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new()).unwrap();
+        std::io::copy(&mut &buf[..], &mut encoder).unwrap();
+        encoder.finish().into_result().unwrap()
+    }
+
+    fn int_polytype() -> PolyType {
+        let mut p = parser::Parser::new("int");
+        let typ_expr = p.parse_type_expression();
+        ast::check::check(ast::walk::Node::TypeExpression(&typ_expr)).unwrap();
+        convert_polytype(&typ_expr, &Default::default()).unwrap()
+    }
+
+    #[test]
+    fn resolves_a_package_from_an_in_memory_map() {
+        let typ = int_polytype();
+        let mut modules = HashMap::new();
+        modules.insert("my/pkg".to_string(), encode_fake_module(typ.clone()));
+
+        let mut importer = EmbeddedImporter {
+            modules: &modules,
+            decoded: HashMap::new(),
+        };
+
+        assert_eq!(importer.import("my/pkg").unwrap(), typ);
+        // A second import hits the `decoded` cache rather than re-decoding.
+        assert_eq!(importer.import("my/pkg").unwrap(), typ);
+    }
+
+    #[test]
+    fn missing_package_is_an_invalid_import_path() {
+        let modules = HashMap::new();
+        let mut importer = EmbeddedImporter {
+            modules: &modules,
+            decoded: HashMap::new(),
+        };
+
+        assert!(matches!(
+            importer.import("nope"),
+            Err(nodes::ErrorKind::InvalidImportPath(_))
+        ));
+    }
+
+    #[test]
+    fn symbol_is_always_none_since_embedded_modules_carry_no_code() {
+        let typ = int_polytype();
+        let mut modules = HashMap::new();
+        modules.insert("my/pkg".to_string(), encode_fake_module(typ));
+
+        let mut importer = EmbeddedImporter {
+            modules: &modules,
+            decoded: HashMap::new(),
+        };
+
+        assert_eq!(importer.symbol("my/pkg", "anything"), None);
+    }
+}
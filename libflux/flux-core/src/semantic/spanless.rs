@@ -0,0 +1,318 @@
+//! Span-insensitive equality and hashing for semantic nodes.
+//!
+//! Two expressions are "spanless equal" when they have the same shape, symbols, and
+//! literal/operator values, regardless of where they appear in the source - every `loc`
+//! field is ignored entirely. This is what lets the vectorizer's common-subexpression
+//! elimination recognize that `r.x + r.y` written twice in two different object
+//! properties is the same computation, even though the two occurrences have distinct
+//! source locations.
+//!
+//! Only node shapes the vectorizer can actually produce are covered; anything else
+//! conservatively compares unequal (and hashes to a fixed value), which only costs a
+//! missed CSE opportunity rather than an incorrect one.
+
+use std::hash::{Hash, Hasher};
+
+use crate::semantic::nodes::{
+    BinaryExpr, ConditionalExpr, Expression, IdentifierExpr, LogicalExpr, MemberExpr, ObjectExpr,
+    UnaryExpr,
+};
+
+/// Span-insensitive equality: same shape, symbols, and values, ignoring `loc`.
+pub trait SpanlessEq {
+    /// Returns whether `self` and `other` are equal, ignoring source location.
+    fn spanless_eq(&self, other: &Self) -> bool;
+}
+
+/// Span-insensitive hashing, consistent with [`SpanlessEq`]: `a.spanless_eq(b)` implies
+/// `a` and `b` spanless-hash to the same value.
+pub trait SpanlessHash {
+    /// Feeds a span-insensitive hash of `self` into `state`.
+    fn spanless_hash<H: Hasher>(&self, state: &mut H);
+}
+
+impl SpanlessEq for Expression {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expression::Identifier(a), Expression::Identifier(b)) => a.spanless_eq(b),
+            (Expression::Member(a), Expression::Member(b)) => a.spanless_eq(b),
+            (Expression::Object(a), Expression::Object(b)) => a.spanless_eq(b),
+            (Expression::Binary(a), Expression::Binary(b)) => a.spanless_eq(b),
+            (Expression::Unary(a), Expression::Unary(b)) => a.spanless_eq(b),
+            (Expression::Logical(a), Expression::Logical(b)) => a.spanless_eq(b),
+            (Expression::Conditional(a), Expression::Conditional(b)) => a.spanless_eq(b),
+            (Expression::Integer(a), Expression::Integer(b)) => a.value == b.value,
+            (Expression::Float(a), Expression::Float(b)) => a.value.to_bits() == b.value.to_bits(),
+            (Expression::Boolean(a), Expression::Boolean(b)) => a.value == b.value,
+            (Expression::StringLit(a), Expression::StringLit(b)) => a.value == b.value,
+            (Expression::Uint(a), Expression::Uint(b)) => a.value == b.value,
+            (Expression::Duration(a), Expression::Duration(b)) => a.value == b.value,
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessHash for Expression {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        // Discriminate by node kind first so two nodes of different kinds never collide
+        // on their payload alone.
+        match self {
+            Expression::Identifier(e) => {
+                0u8.hash(state);
+                e.spanless_hash(state);
+            }
+            Expression::Member(e) => {
+                1u8.hash(state);
+                e.spanless_hash(state);
+            }
+            Expression::Object(e) => {
+                2u8.hash(state);
+                e.spanless_hash(state);
+            }
+            Expression::Binary(e) => {
+                3u8.hash(state);
+                e.spanless_hash(state);
+            }
+            Expression::Unary(e) => {
+                4u8.hash(state);
+                e.spanless_hash(state);
+            }
+            Expression::Logical(e) => {
+                5u8.hash(state);
+                e.spanless_hash(state);
+            }
+            Expression::Conditional(e) => {
+                6u8.hash(state);
+                e.spanless_hash(state);
+            }
+            Expression::Integer(e) => {
+                7u8.hash(state);
+                e.value.hash(state);
+            }
+            Expression::Float(e) => {
+                8u8.hash(state);
+                e.value.to_bits().hash(state);
+            }
+            Expression::Boolean(e) => {
+                9u8.hash(state);
+                e.value.hash(state);
+            }
+            Expression::StringLit(e) => {
+                10u8.hash(state);
+                e.value.hash(state);
+            }
+            Expression::Uint(e) => {
+                11u8.hash(state);
+                e.value.hash(state);
+            }
+            Expression::Duration(e) => {
+                12u8.hash(state);
+                e.value.hash(state);
+            }
+            // Unsupported shapes all hash the same; they'll still compare unequal via
+            // `spanless_eq`, so this only costs a hash bucket collision, not correctness.
+            _ => 255u8.hash(state),
+        }
+    }
+}
+
+impl SpanlessEq for IdentifierExpr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl SpanlessHash for IdentifierExpr {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl SpanlessEq for MemberExpr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.property == other.property && self.object.spanless_eq(&other.object)
+    }
+}
+
+impl SpanlessHash for MemberExpr {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        self.property.hash(state);
+        self.object.spanless_hash(state);
+    }
+}
+
+impl SpanlessEq for ObjectExpr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.properties.len() == other.properties.len()
+            && self
+                .properties
+                .iter()
+                .zip(&other.properties)
+                .all(|(a, b)| a.key.name == b.key.name && a.value.spanless_eq(&b.value))
+            && match (&self.with, &other.with) {
+                (Some(a), Some(b)) => a.spanless_eq(b),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+impl SpanlessHash for ObjectExpr {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        for p in &self.properties {
+            p.key.name.hash(state);
+            p.value.spanless_hash(state);
+        }
+        if let Some(with) = &self.with {
+            with.spanless_hash(state);
+        }
+    }
+}
+
+impl SpanlessEq for BinaryExpr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.operator == other.operator
+            && self.left.spanless_eq(&other.left)
+            && self.right.spanless_eq(&other.right)
+    }
+}
+
+impl SpanlessHash for BinaryExpr {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        self.operator.hash(state);
+        self.left.spanless_hash(state);
+        self.right.spanless_hash(state);
+    }
+}
+
+impl SpanlessEq for UnaryExpr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.operator == other.operator && self.argument.spanless_eq(&other.argument)
+    }
+}
+
+impl SpanlessHash for UnaryExpr {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        self.operator.hash(state);
+        self.argument.spanless_hash(state);
+    }
+}
+
+impl SpanlessEq for LogicalExpr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.operator == other.operator
+            && self.left.spanless_eq(&other.left)
+            && self.right.spanless_eq(&other.right)
+    }
+}
+
+impl SpanlessHash for LogicalExpr {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        self.operator.hash(state);
+        self.left.spanless_hash(state);
+        self.right.spanless_hash(state);
+    }
+}
+
+impl SpanlessEq for ConditionalExpr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.test.spanless_eq(&other.test)
+            && self.consequent.spanless_eq(&other.consequent)
+            && self.alternate.spanless_eq(&other.alternate)
+    }
+}
+
+impl SpanlessHash for ConditionalExpr {
+    fn spanless_hash<H: Hasher>(&self, state: &mut H) {
+        self.test.spanless_hash(state);
+        self.consequent.spanless_hash(state);
+        self.alternate.spanless_hash(state);
+    }
+}
+
+/// Wraps an `Expression` so it can be used as a `HashMap`/`HashSet` key via
+/// [`SpanlessEq`]/[`SpanlessHash`] instead of a location-sensitive derived `PartialEq`/`Hash`.
+#[derive(Clone)]
+pub struct HashedExpr(pub Expression);
+
+impl PartialEq for HashedExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.spanless_eq(&other.0)
+    }
+}
+
+impl Eq for HashedExpr {}
+
+impl Hash for HashedExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.spanless_hash(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        ast::Operator,
+        semantic::{nodes::IdentifierExpr, types::MonoType, Symbol},
+    };
+
+    // Builds `r.<property>`. Every node below uses `Default::default()` for `loc`, so these
+    // tests compare shapes, symbols and operators only; they never build two occurrences
+    // with different source locations.
+    fn member(property: &str) -> Expression {
+        Expression::Member(Box::new(MemberExpr {
+            loc: Default::default(),
+            typ: MonoType::INT,
+            object: Expression::Identifier(Box::new(IdentifierExpr {
+                loc: Default::default(),
+                typ: MonoType::INT,
+                name: Symbol::from("r"),
+            })),
+            property: property.to_string(),
+        }))
+    }
+
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Binary(Box::new(BinaryExpr {
+            loc: Default::default(),
+            typ: MonoType::INT,
+            operator: Operator::AdditionOperator,
+            left,
+            right,
+        }))
+    }
+
+    #[test]
+    fn r_x_plus_r_y_is_spanless_equal_across_occurrences() {
+        // The same `r.x + r.y` computation, built twice independently - exactly what the
+        // vectorizer's CSE pass needs to recognize as one shared subexpression rather than
+        // two, when it appears in two different object properties.
+        let first = add(member("x"), member("y"));
+        let second = add(member("x"), member("y"));
+
+        assert!(first.spanless_eq(&second));
+
+        let mut seen = HashSet::new();
+        assert!(seen.insert(HashedExpr(first)));
+        assert!(
+            !seen.insert(HashedExpr(second)),
+            "a second occurrence of the same subexpression should already be `seen`, \
+             which is what lets CSE emit it only once",
+        );
+    }
+
+    #[test]
+    fn different_properties_are_not_spanless_equal() {
+        let x_plus_y = add(member("x"), member("y"));
+        let x_plus_z = add(member("x"), member("z"));
+
+        assert!(!x_plus_y.spanless_eq(&x_plus_z));
+
+        let mut seen = HashSet::new();
+        assert!(seen.insert(HashedExpr(x_plus_y)));
+        assert!(seen.insert(HashedExpr(x_plus_z)));
+    }
+}
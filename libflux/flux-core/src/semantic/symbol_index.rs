@@ -0,0 +1,227 @@
+//! A fuzzy-searchable index of every exported symbol across the packages a [`Flux`] database
+//! knows about, for editor tooling (completion, "did you mean") that needs to search *all*
+//! packages at once rather than one `PackageExports::lookup_symbol` at a time.
+//!
+//! The index is a single `fst::Map` keyed by exported name, built by [`SymbolIndexBuilder`]
+//! from each package's [`PackageExports`]. Because an `fst::Map` can only carry one value per
+//! key, entries are sorted and grouped by name first: the map's value for a name is the index
+//! of that name's first entry in a side table, and every later entry sharing the name sits in
+//! the run immediately after it. This is what lets two unrelated packages both export, say,
+//! `mean`, without one clobbering the other.
+//!
+//! Rebuilding is cheap to make incremental: [`Flux::symbol_index`] is `#[salsa::transparent]`,
+//! so it always re-walks `package_paths`, but each `semantic_package` call it makes along the
+//! way is itself a memoized, salsa-invalidated query - only the packages whose source actually
+//! changed since the last build get re-inferred, the rest come back from salsa's cache.
+
+use std::collections::HashSet;
+
+use fst::{automaton::Levenshtein, Automaton, IntoStreamer, Streamer};
+
+use crate::semantic::{types::PolyType, PackageExports, Symbol};
+
+/// A single exported binding reachable through the index: the package it's exported from,
+/// the symbol itself, and its inferred type.
+#[derive(Debug, Clone)]
+pub struct SymbolEntry {
+    /// Import path of the package that exports this symbol.
+    pub package: String,
+    /// The exported symbol.
+    pub symbol: Symbol,
+    /// The symbol's inferred type.
+    pub typ: PolyType,
+}
+
+/// A fuzzy-searchable index of exported symbol names across every package indexed into it.
+///
+/// Built by [`SymbolIndexBuilder`]; see the module docs for how duplicate names across
+/// packages are represented.
+pub struct SymbolIndex {
+    map: fst::Map<Vec<u8>>,
+    // Sorted and grouped by the name entries are keyed under, so every entry sharing a name
+    // with `entries[i]` sits in the contiguous run starting at `i`.
+    entries: Vec<(String, SymbolEntry)>,
+}
+
+impl SymbolIndex {
+    /// An empty index, returned when there's nothing to build (e.g. no packages yet resolved).
+    pub fn empty() -> Self {
+        SymbolIndexBuilder::new().build()
+    }
+
+    /// Every entry whose name starts with `prefix`, e.g. for completing `agg` while typing.
+    pub fn prefix(&self, prefix: &str) -> Vec<&SymbolEntry> {
+        let automaton = fst::automaton::Str::new(prefix).starts_with();
+        self.search(automaton)
+    }
+
+    /// Every entry within Levenshtein distance `max_distance` of `query`, e.g. for finding
+    /// `window` when the user typed `widnow`.
+    ///
+    /// Returns no matches (rather than erroring) if `query` is too long for `fst`'s
+    /// Levenshtein automaton to build.
+    pub fn fuzzy(&self, query: &str, max_distance: u32) -> Vec<&SymbolEntry> {
+        match Levenshtein::new(query, max_distance) {
+            Ok(automaton) => self.search(automaton),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn search<A: Automaton>(&self, automaton: A) -> Vec<&SymbolEntry> {
+        let mut out = Vec::new();
+        let mut stream = self.map.search(automaton).into_stream();
+        while let Some((_name, start)) = stream.next() {
+            let start = start as usize;
+            let name = &self.entries[start].0;
+            let mut i = start;
+            while i < self.entries.len() && &self.entries[i].0 == name {
+                out.push(&self.entries[i].1);
+                i += 1;
+            }
+        }
+        out
+    }
+}
+
+/// Incrementally builds a [`SymbolIndex`] out of one or more packages' [`PackageExports`].
+pub struct SymbolIndexBuilder {
+    seen: HashSet<(String, Symbol)>,
+    entries: Vec<(String, SymbolEntry)>,
+}
+
+impl SymbolIndexBuilder {
+    /// A builder with nothing indexed yet.
+    pub fn new() -> Self {
+        Self {
+            seen: HashSet::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds every binding `exports` (the result of resolving `package`) makes visible.
+    ///
+    /// A `(package, symbol)` pair already indexed under an earlier call is skipped here
+    /// rather than indexed a second time - that only happens if the same package is added
+    /// twice, and keeps a repeated `add_package` call idempotent. It deliberately does *not*
+    /// key on `symbol` alone: two unrelated packages each exporting a same-named binding
+    /// (e.g. two packages that both define `f`) are different bindings and must both end up
+    /// indexed, not collapsed into one because `Symbol` compares equal on name alone.
+    pub fn add_package(&mut self, package: &str, exports: &PackageExports) {
+        for (symbol, typ) in exports.iter() {
+            if !self.seen.insert((package.to_string(), symbol.clone())) {
+                continue;
+            }
+            self.entries.push((
+                symbol.to_string(),
+                SymbolEntry {
+                    package: package.to_string(),
+                    symbol: symbol.clone(),
+                    typ: typ.clone(),
+                },
+            ));
+        }
+    }
+
+    /// Finishes the index, building the underlying `fst::Map`.
+    pub fn build(mut self) -> SymbolIndex {
+        self.entries
+            .sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut builder = fst::MapBuilder::memory();
+        let mut i = 0;
+        while i < self.entries.len() {
+            let name = self.entries[i].0.clone();
+            // `entries` is sorted above and this is the first occurrence of `name` seen in
+            // the walk, so each name is inserted into the fst map exactly once, in order.
+            builder
+                .insert(name.as_bytes(), i as u64)
+                .expect("entries are sorted by name with no duplicate insertions");
+            while i < self.entries.len() && self.entries[i].0 == name {
+                i += 1;
+            }
+        }
+
+        let map = fst::Map::new(builder.into_inner().expect("fst map builder never fails in memory"))
+            .expect("bytes just produced by MapBuilder are a valid fst::Map");
+
+        SymbolIndex {
+            map,
+            entries: self.entries,
+        }
+    }
+}
+
+impl Default for SymbolIndexBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use super::*;
+    use crate::semantic::bootstrap::{Database, Flux};
+
+    // Builds an index over two unrelated packages that each declare their own `f`, plus one
+    // declaring `window` for the fuzzy tests to reach by a misspelling - real inference
+    // output, rather than hand-built `SymbolEntry`s, is what actually exercises whether the `seen`-based dedup in
+    // `add_package` keys on `(package, symbol)` rather than `symbol` alone, since two
+    // independently-constructed `Symbol::from("f")`s compare equal regardless of package.
+    fn two_package_index() -> Arc<SymbolIndex> {
+        let mut db = Database::default();
+        db.set_use_prelude(false);
+        db.set_source("a/a.flux".into(), "f = () => 1".into());
+        db.set_source("b/b.flux".into(), "f = () => 2".into());
+        db.set_source("c/c.flux".into(), "window = () => 3".into());
+        db.symbol_index()
+    }
+
+    #[test]
+    fn prefix_completes_across_packages_without_collapsing_duplicate_names() {
+        let index = two_package_index();
+
+        let hits = index.prefix("f");
+        assert_eq!(
+            hits.len(),
+            2,
+            "package `a`'s `f` and package `b`'s `f` are different bindings and should both \
+             be indexed, not collapsed by the `seen` dedup: {:?}",
+            hits.iter().map(|e| &e.package).collect::<Vec<_>>()
+        );
+
+        let packages: HashSet<&str> = hits.iter().map(|e| e.package.as_str()).collect();
+        assert!(packages.contains("a"));
+        assert!(packages.contains("b"));
+    }
+
+    #[test]
+    fn fuzzy_finds_a_near_miss_spelling() {
+        let index = two_package_index();
+
+        // "widnow" (transposed letters) against `window` - edit distance 2 (two substitutions).
+        let hits = index.fuzzy("widnow", 2);
+        assert!(
+            hits.iter().any(|e| e.symbol.to_string() == "window"),
+            "expected a fuzzy match for `window`, got {:?}",
+            hits.iter().map(|e| e.symbol.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn fuzzy_respects_max_distance() {
+        let index = two_package_index();
+
+        // Same near-miss spelling, but with no edit-distance budget to find it.
+        assert!(index.fuzzy("widnow", 0).is_empty());
+    }
+
+    #[test]
+    fn empty_index_has_no_hits() {
+        let index = SymbolIndex::empty();
+
+        assert!(index.prefix("f").is_empty());
+        assert!(index.fuzzy("f", 2).is_empty());
+    }
+}
@@ -3,10 +3,13 @@ use std::collections::HashMap;
 use crate::{
     errors::located,
     semantic::{
+        constant::{collect_constant_bindings, ConstantEnv},
         nodes::{
-            Block, ErrorKind, Expression, FunctionExpr, IdentifierExpr, MemberExpr, ObjectExpr,
-            Package, Property, Result, ReturnStmt,
+            BinaryExpr, Block, ConditionalExpr, Expression, ErrorKind, FunctionExpr, Identifier,
+            IdentifierExpr, LogicalExpr, MemberExpr, ObjectExpr, Package, Property, Result,
+            ReturnStmt, UnaryExpr, VariableAssgn,
         },
+        spanless::HashedExpr,
         types::{self, Function, Label, MonoType},
         Symbol,
     },
@@ -40,14 +43,32 @@ pub fn vectorize(pkg: &mut Package) -> Result<()> {
 
 type VectorizeEnv = HashMap<Symbol, MonoType>;
 
+// Types whose scalar element supports the element-wise kernels the vectorizer emits.
+// Anything structural (records, functions, vectors-of-vectors, ...) has to be narrowed
+// to a scalar member first (see `Expression::Member` below) before it can take part in
+// a vectorized operation.
+fn is_vectorizable_scalar(typ: &MonoType) -> bool {
+    !matches!(typ, MonoType::Record(_) | MonoType::Fun(_) | MonoType::Vector(_))
+}
+
 impl Expression {
-    fn vectorize(&self, env: &VectorizeEnv) -> Result<Self> {
+    fn vectorize(&self, env: &VectorizeEnv, constants: &ConstantEnv) -> Result<Self> {
+        // Fold constant subtrees (bare literals, operators over them, and identifiers bound
+        // in `constants`) to a single broadcast value before descending into
+        // operator-specific vectorization, e.g. the `(2 + 3)` in `r.x * (2 + 3)` becomes a
+        // broadcast `5` rather than a vectorized addition.
+        if let Some(constant) = self.fold_constants(constants) {
+            if let Some(broadcast) = constant.into_broadcast(self.loc().clone(), self.type_of()) {
+                return Ok(broadcast);
+            }
+        }
+
         Ok(match self {
             Expression::Identifier(identifier) => {
                 Expression::Identifier(identifier.vectorize(env)?)
             }
             Expression::Member(member) => {
-                let object = member.object.vectorize(env)?;
+                let object = member.object.vectorize(env, constants)?;
                 let typ = object.type_of();
                 Expression::Member(Box::new(MemberExpr {
                     loc: member.loc.clone(),
@@ -68,6 +89,120 @@ impl Expression {
                     property: member.property.clone(),
                 }))
             }
+            Expression::Binary(expr) => {
+                let left = expr.left.vectorize(env, constants)?;
+                let right = expr.right.vectorize(env, constants)?;
+                let elem = expr.typ.clone();
+                if !is_vectorizable_scalar(&elem) {
+                    return Err(located(
+                        expr.loc.clone(),
+                        ErrorKind::UnableToVectorize(format!(
+                            "Unsupported operand type `{}` for vectorized `{}`",
+                            elem, expr.operator
+                        )),
+                    ));
+                }
+                Expression::Binary(Box::new(BinaryExpr {
+                    loc: expr.loc.clone(),
+                    typ: MonoType::vector(elem),
+                    operator: expr.operator.clone(),
+                    left,
+                    right,
+                }))
+            }
+            Expression::Unary(expr) => {
+                let argument = expr.argument.vectorize(env, constants)?;
+                let elem = expr.typ.clone();
+                if !is_vectorizable_scalar(&elem) {
+                    return Err(located(
+                        expr.loc.clone(),
+                        ErrorKind::UnableToVectorize(format!(
+                            "Unsupported operand type `{}` for vectorized `{}`",
+                            elem, expr.operator
+                        )),
+                    ));
+                }
+                Expression::Unary(Box::new(UnaryExpr {
+                    loc: expr.loc.clone(),
+                    typ: MonoType::vector(elem),
+                    operator: expr.operator.clone(),
+                    argument,
+                }))
+            }
+            Expression::Conditional(expr) => {
+                let test = expr.test.vectorize(env, constants)?;
+                let consequent = expr.consequent.vectorize(env, constants)?;
+                let alternate = expr.alternate.vectorize(env, constants)?;
+
+                // Flux never unifies `int` with `int?`, so a conditional's two (scalar)
+                // branches already agree on element type by the time we get here, unless
+                // one side is `null` - its branch infers to `T?` while the other stays
+                // plain `T`. That one mismatch is expected: it's lowered to a masked
+                // vector (`Value::Masked` in `flux-vm`, filled in at the lanes that chose
+                // the non-`null` branch) rather than rejected like every other mismatch
+                // still is.
+                let consequent_elem = expr.consequent.type_of();
+                let alternate_elem = expr.alternate.type_of();
+                let (elem, masked) = if consequent_elem == alternate_elem {
+                    (consequent_elem, false)
+                } else if matches!(&alternate_elem, MonoType::Optional(inner) if **inner == consequent_elem)
+                {
+                    (consequent_elem, true)
+                } else if matches!(&consequent_elem, MonoType::Optional(inner) if **inner == alternate_elem)
+                {
+                    (alternate_elem, true)
+                } else {
+                    return Err(located(
+                        expr.alternate.loc().clone(),
+                        ErrorKind::UnableToVectorize(format!(
+                            "Conditional branches have different types, `{}` and `{}`",
+                            consequent_elem, alternate_elem
+                        )),
+                    ));
+                };
+                if !is_vectorizable_scalar(&elem) {
+                    return Err(located(
+                        expr.loc.clone(),
+                        ErrorKind::UnableToVectorize(format!(
+                            "Unsupported operand type `{}` for vectorized conditional",
+                            elem
+                        )),
+                    ));
+                }
+
+                Expression::Conditional(Box::new(ConditionalExpr {
+                    loc: expr.loc.clone(),
+                    typ: if masked {
+                        MonoType::vector(MonoType::Optional(Box::new(elem)))
+                    } else {
+                        MonoType::vector(elem)
+                    },
+                    test,
+                    consequent,
+                    alternate,
+                }))
+            }
+            Expression::Logical(expr) => {
+                let left = expr.left.vectorize(env, constants)?;
+                let right = expr.right.vectorize(env, constants)?;
+                let elem = expr.typ.clone();
+                if !is_vectorizable_scalar(&elem) {
+                    return Err(located(
+                        expr.loc.clone(),
+                        ErrorKind::UnableToVectorize(format!(
+                            "Unsupported operand type `{}` for vectorized `{}`",
+                            elem, expr.operator
+                        )),
+                    ));
+                }
+                Expression::Logical(Box::new(LogicalExpr {
+                    loc: expr.loc.clone(),
+                    typ: MonoType::vector(elem),
+                    operator: expr.operator.clone(),
+                    left,
+                    right,
+                }))
+            }
             _ => {
                 return Err(located(
                     self.loc().clone(),
@@ -90,6 +225,84 @@ impl IdentifierExpr {
     }
 }
 
+/// Performs common-subexpression elimination across the vectorized value of every property
+/// in a returned record: the first time a (spanless-equal) subexpression is vectorized it is
+/// bound to a synthetic `let`, and every later occurrence is rewritten to reference that
+/// binding instead of re-emitting the same vector op. Only a whole property value is looked
+/// up; a repeated subexpression nested inside a larger property value is vectorized again at
+/// each occurrence.
+#[derive(Default)]
+struct Cse {
+    seen: HashMap<HashedExpr, (Symbol, MonoType)>,
+    bindings: Vec<(Symbol, Expression)>,
+}
+
+impl Cse {
+    fn vectorize(
+        &mut self,
+        expr: &Expression,
+        env: &VectorizeEnv,
+        constants: &ConstantEnv,
+    ) -> Result<Expression> {
+        // Only bother sharing actual operations: a bare identifier or member access is
+        // already as cheap to re-emit as a reference to a synthetic binding would be.
+        let is_worth_sharing = matches!(
+            expr,
+            Expression::Binary(_)
+                | Expression::Unary(_)
+                | Expression::Logical(_)
+                | Expression::Conditional(_)
+        );
+        if !is_worth_sharing {
+            return expr.vectorize(env, constants);
+        }
+
+        let key = HashedExpr(expr.clone());
+        if let Some((symbol, typ)) = self.seen.get(&key) {
+            return Ok(Expression::Identifier(Box::new(IdentifierExpr {
+                loc: expr.loc().clone(),
+                typ: typ.clone(),
+                name: symbol.clone(),
+            })));
+        }
+
+        let vectorized = expr.vectorize(env, constants)?;
+        let typ = vectorized.type_of();
+        let symbol = Symbol::from(format!("_vectorize_cse{}", self.bindings.len()));
+        self.seen.insert(key, (symbol.clone(), typ.clone()));
+        self.bindings.push((symbol.clone(), vectorized));
+
+        Ok(Expression::Identifier(Box::new(IdentifierExpr {
+            loc: expr.loc().clone(),
+            typ,
+            name: symbol,
+        })))
+    }
+
+    /// Wraps `argument` in a `return`, prefixed by a `let` for every shared subexpression
+    /// found along the way.
+    fn finish(self, loc: crate::ast::SourceLocation, argument: Expression) -> Block {
+        let mut block = Block::Return(ReturnStmt {
+            loc: loc.clone(),
+            argument,
+        });
+        for (symbol, init) in self.bindings.into_iter().rev() {
+            block = Block::Variable(
+                Box::new(VariableAssgn::new(
+                    Identifier {
+                        loc: loc.clone(),
+                        name: symbol,
+                    },
+                    init,
+                    loc.clone(),
+                )),
+                Box::new(block),
+            );
+        }
+        block
+    }
+}
+
 impl FunctionExpr {
     fn vectorize(&self) -> Result<Self> {
         if self.params.len() == 1 && self.params[0].key.name == "r" {
@@ -127,54 +340,34 @@ impl FunctionExpr {
                         ErrorKind::UnableToVectorize("Unable to vectorize statements".into()),
                     ))
                 }
-                // XXX: sean (January 14 2022) - The only type of function expression
-                // currently supported for vectorization is one whose body contains only
-                // a single object expression, the fields of which only reference members of
-                // `r` and do not include any kind of operation, literal, or logical expression.
-                //
-                // We may support other expression types in the future.
+                // The function body must be a single returned object expression, but each
+                // property value may now be any vectorizable expression (member access,
+                // arithmetic/logical operators, literals, ...) rather than only a bare
+                // member of `r`.
                 Block::Return(e) => {
+                    // `collect_constant_bindings` only walks leading `Variable` blocks, and a
+                    // body with any is rejected above, so this is always empty here: literal
+                    // subtrees such as `(2 + 3)` still fold, identifiers never do.
+                    let constants = collect_constant_bindings(&self.body);
+                    let mut cse = Cse::default();
                     let argument = match &e.argument {
                         Expression::Object(e) => {
                             let properties = e
                                 .properties
                                 .iter()
                                 .map(|p| {
-                                    let mem = match &p.value {
-                                        Expression::Member(m) => m.clone(),
-                                        _ => {
-                                            return Err(located(
-                                                self.body.loc().clone(),
-                                                ErrorKind::UnableToVectorize(
-                                                    "expression type cannot be vectorized".into(),
-                                                ),
-                                            ))
-                                        }
-                                    };
-                                    match mem.object {
-                                        Expression::Identifier(i) if i.name == "r" => {
-                                            Ok(Property {
-                                                loc: p.loc.clone(),
-                                                key: p.key.clone(),
-                                                value: p.value.vectorize(&env)?,
-                                            })
-                                        }
-                                        _ => {
-                                            return Err(located(
-                                                self.body.loc().clone(),
-                                                ErrorKind::UnableToVectorize(
-                                                    "expression type cannot be vectorized".into(),
-                                                ),
-                                            ))
-                                        }
-                                    }
+                                    Ok(Property {
+                                        loc: p.loc.clone(),
+                                        key: p.key.clone(),
+                                        value: cse.vectorize(&p.value, &env, &constants)?,
+                                    })
                                 })
                                 .collect::<Result<Vec<_>>>()?;
 
                             let with = e
                                 .with
                                 .as_ref()
-                                .map(|with| with.vectorize(&env))
+                                .map(|with| with.vectorize(&env, &constants))
                                 .transpose()?;
 
                             Expression::Object(Box::new(ObjectExpr {
@@ -199,10 +392,7 @@ impl FunctionExpr {
                             ))
                         }
                     };
-                    Block::Return(ReturnStmt {
-                        loc: e.loc.clone(),
-                        argument,
-                    })
+                    cse.finish(e.loc.clone(), argument)
                 }
             };
             Ok(FunctionExpr {
@@ -228,4 +418,267 @@ impl FunctionExpr {
             ))
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::{
+        nodes::{FunctionParameter, Identifier},
+        types::Property as TypeProperty,
+    };
+
+    fn loc() -> crate::ast::SourceLocation {
+        Default::default()
+    }
+
+    fn record_type(fields: Vec<(&str, MonoType)>) -> MonoType {
+        MonoType::from(types::Record::new(
+            fields
+                .into_iter()
+                .map(|(k, v)| TypeProperty { k: k.into(), v }),
+            None,
+        ))
+    }
+
+    fn member(object: &str, property: &str, object_typ: MonoType, field_typ: MonoType) -> Expression {
+        Expression::Member(Box::new(MemberExpr {
+            loc: loc(),
+            typ: field_typ,
+            object: Expression::Identifier(Box::new(IdentifierExpr {
+                loc: loc(),
+                typ: object_typ,
+                name: Symbol::from(object),
+            })),
+            property: property.to_string(),
+        }))
+    }
+
+    // `(r) => ({z: <value>})`, not yet vectorized - a minimal `map`-shaped function with a
+    // single property, the shape `FunctionExpr::vectorize` requires.
+    fn scalar_map_fn(param_typ: MonoType, value: Expression) -> FunctionExpr {
+        let value_typ = value.type_of();
+        FunctionExpr {
+            loc: loc(),
+            typ: MonoType::from(Function {
+                pipe: None,
+                req: [("r".to_string(), param_typ.clone())].into_iter().collect(),
+                opt: Default::default(),
+                retn: record_type(vec![("z", value_typ.clone())]),
+            }),
+            params: vec![FunctionParameter {
+                loc: loc(),
+                key: Identifier {
+                    loc: loc(),
+                    name: Symbol::from("r"),
+                },
+                default: None,
+                is_pipe: false,
+            }],
+            body: Block::Return(ReturnStmt {
+                loc: loc(),
+                argument: Expression::Object(Box::new(ObjectExpr {
+                    loc: loc(),
+                    typ: record_type(vec![("z", value_typ)]),
+                    with: None,
+                    properties: vec![Property {
+                        loc: loc(),
+                        key: Identifier {
+                            loc: loc(),
+                            name: Symbol::from("z"),
+                        },
+                        value,
+                    }],
+                })),
+            }),
+            vectorized: None,
+        }
+    }
+
+    #[test]
+    fn vectorizes_binary_arithmetic_over_record_fields() {
+        let param_typ = record_type(vec![("x", MonoType::INT), ("y", MonoType::INT)]);
+        let value = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            typ: MonoType::INT,
+            operator: crate::ast::Operator::AdditionOperator,
+            left: member("r", "x", param_typ.clone(), MonoType::INT),
+            right: member("r", "y", param_typ.clone(), MonoType::INT),
+        }));
+        let function = scalar_map_fn(param_typ, value);
+
+        let vectorized = function
+            .vectorize()
+            .expect("a simple arithmetic map body should vectorize");
+
+        // `Cse` binds the `r.x + r.y` property value to a synthetic `let` ahead of the return.
+        let mut block = &vectorized.body;
+        while let Block::Variable(_, next) = block {
+            block = next;
+        }
+        match block {
+            Block::Return(ReturnStmt {
+                argument: Expression::Object(obj),
+                ..
+            }) => {
+                assert_eq!(obj.properties[0].value.type_of(), MonoType::vector(MonoType::INT));
+            }
+            other => panic!("expected a returned object body, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn logical_expression_over_an_unsupported_element_type_cannot_be_vectorized() {
+        let inner = record_type(vec![("a", MonoType::BOOL)]);
+        let param_typ = record_type(vec![("x", inner.clone())]);
+        // `r.x` is itself a record, not a scalar - the `Logical` case's
+        // `is_vectorizable_scalar` guard should reject it rather than emit a nonsensical
+        // vectorized comparison of two record columns.
+        let value = Expression::Logical(Box::new(LogicalExpr {
+            loc: loc(),
+            typ: inner.clone(),
+            operator: crate::ast::Operator::AndOperator,
+            left: member("r", "x", param_typ.clone(), inner.clone()),
+            right: member("r", "x", param_typ.clone(), inner),
+        }));
+        let function = scalar_map_fn(param_typ, value);
+
+        let err = function.vectorize().unwrap_err();
+        assert!(
+            err.to_string().contains("Unsupported operand type"),
+            "expected an UnableToVectorize error naming the unsupported element type, got: {}",
+            err,
+        );
+    }
+
+    #[test]
+    fn conditional_with_mismatched_branch_types_cannot_be_vectorized() {
+        let param_typ = record_type(vec![
+            ("cond", MonoType::BOOL),
+            ("x", MonoType::INT),
+            ("y", MonoType::STRING),
+        ]);
+        let value = Expression::Conditional(Box::new(ConditionalExpr {
+            loc: loc(),
+            typ: MonoType::INT,
+            test: member("r", "cond", param_typ.clone(), MonoType::BOOL),
+            consequent: member("r", "x", param_typ.clone(), MonoType::INT),
+            // Neither side is `null`, so this isn't the one mismatch the vectorizer allows
+            // (a plain `T` branch against an `T?` one) - `int` and `string` should be
+            // rejected outright.
+            alternate: member("r", "y", param_typ.clone(), MonoType::STRING),
+        }));
+        let function = scalar_map_fn(param_typ, value);
+
+        let err = function.vectorize().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Conditional branches have different types"),
+            "expected an UnableToVectorize error naming the mismatched branch types, got: {}",
+            err,
+        );
+    }
+
+    #[test]
+    fn cse_shares_a_single_binding_for_a_duplicated_subexpression() {
+        let param_typ = record_type(vec![("x", MonoType::INT), ("y", MonoType::INT)]);
+        let shared = |param_typ: MonoType| {
+            Expression::Binary(Box::new(BinaryExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                operator: crate::ast::Operator::AdditionOperator,
+                left: member("r", "x", param_typ.clone(), MonoType::INT),
+                right: member("r", "y", param_typ, MonoType::INT),
+            }))
+        };
+
+        let function = FunctionExpr {
+            loc: loc(),
+            typ: MonoType::from(Function {
+                pipe: None,
+                req: [("r".to_string(), param_typ.clone())].into_iter().collect(),
+                opt: Default::default(),
+                retn: record_type(vec![("z1", MonoType::INT), ("z2", MonoType::INT)]),
+            }),
+            params: vec![FunctionParameter {
+                loc: loc(),
+                key: Identifier {
+                    loc: loc(),
+                    name: Symbol::from("r"),
+                },
+                default: None,
+                is_pipe: false,
+            }],
+            body: Block::Return(ReturnStmt {
+                loc: loc(),
+                argument: Expression::Object(Box::new(ObjectExpr {
+                    loc: loc(),
+                    typ: record_type(vec![("z1", MonoType::INT), ("z2", MonoType::INT)]),
+                    with: None,
+                    // Both properties compute the exact same (spanless-equal) `r.x + r.y` -
+                    // the duplicate CSE is supposed to collapse into a single binding.
+                    properties: vec![
+                        Property {
+                            loc: loc(),
+                            key: Identifier {
+                                loc: loc(),
+                                name: Symbol::from("z1"),
+                            },
+                            value: shared(param_typ.clone()),
+                        },
+                        Property {
+                            loc: loc(),
+                            key: Identifier {
+                                loc: loc(),
+                                name: Symbol::from("z2"),
+                            },
+                            value: shared(param_typ.clone()),
+                        },
+                    ],
+                })),
+            }),
+            vectorized: None,
+        };
+
+        let vectorized = function
+            .vectorize()
+            .expect("a duplicated r.x + r.y subexpression should still vectorize");
+
+        let mut bindings = 0;
+        let mut block = &vectorized.body;
+        while let Block::Variable(_, next) = block {
+            bindings += 1;
+            block = next;
+        }
+        assert_eq!(
+            bindings, 1,
+            "expected exactly one synthetic CSE binding, got body: {:?}",
+            vectorized.body,
+        );
+
+        match block {
+            Block::Return(ReturnStmt {
+                argument: Expression::Object(obj),
+                ..
+            }) => {
+                let names: Vec<_> = obj
+                    .properties
+                    .iter()
+                    .map(|p| match &p.value {
+                        Expression::Identifier(id) => id.name.to_string(),
+                        other => panic!(
+                            "expected both properties to reference the shared CSE binding, got {:?}",
+                            other
+                        ),
+                    })
+                    .collect();
+                assert_eq!(
+                    names[0], names[1],
+                    "both properties should reference the same CSE binding instead of \
+                     re-emitting the addition twice",
+                );
+            }
+            other => panic!("expected a returned object, got {:?}", other),
+        }
+    }
 }
\ No newline at end of file
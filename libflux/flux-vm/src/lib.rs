@@ -1,31 +1,931 @@
-// use fluxcore::semantic;
-//
-// pub struct Machine<T: Runtime> {
-//     runtime: T
-// }
-//
-// impl <T: Runtime> Machine<T> {
-//     pub fn new(runtime: impl Runtime) -> Box<Machine<T>> {
-//         return Box::new(Machine { runtime });
-//     }
-//
-//     pub fn run(&self) {
-//
-//     }
-// }
+//! A small vectorized execution runtime for compiled Flux functions.
+//!
+//! `fluxcore::semantic::vectorize` lowers a `map`-shaped `FunctionExpr` into an element-wise
+//! form (the `vectorized` field) that operates on whole columns instead of one row at a time.
+//! This crate is the other half: [`Machine`] walks that vectorized body and dispatches each
+//! node to a [`Runtime`], which actually performs the columnar math. Any function the
+//! vectorizer couldn't lower (`vectorized: None`) still runs, just one row at a time.
 
+use std::collections::HashMap;
 
+use fluxcore::{
+    ast::Operator,
+    semantic::nodes::{Block, Expression, FunctionExpr},
+};
 
+/// A single evaluated value: either a whole column (the common case, under vectorized
+/// evaluation) or a single scalar (under the row-at-a-time fallback).
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
+    /// A scalar, as bound/produced while evaluating one row of the fallback path.
     Int(i64),
     Float(f64),
+    Bool(bool),
+    String(String),
+
+    /// A column, as bound/produced while evaluating a vectorized body over a whole batch.
+    IntVector(Vec<i64>),
+    FloatVector(Vec<f64>),
+    BoolVector(Vec<bool>),
+    StringVector(Vec<String>),
+
+    /// A column with a validity mask: `values[i]` is only meaningful where `valid[i]` is
+    /// `true`. Produced by a `select` whose two branches don't agree lane-by-lane, e.g.
+    /// because one branch is a broadcast `null`.
+    Masked { values: Box<Value>, valid: Vec<bool> },
+
+    /// A `null` scalar, as `row_value` yields for an invalid lane of a [`Value::Masked`]
+    /// column. `select` turns a `Null` branch into a [`Value::Masked`] rather than a real
+    /// column; every other kernel rejects it with a `TypeMismatch`.
+    Null,
+
+    /// A record of named columns (or scalars, under the fallback), e.g. the result of
+    /// evaluating `{r with z: ...}`.
+    Record(HashMap<String, Value>),
+}
+
+impl Value {
+    /// The number of rows in this column, or `1` for a scalar value.
+    pub fn len(&self) -> usize {
+        match self {
+            Value::IntVector(v) => v.len(),
+            Value::FloatVector(v) => v.len(),
+            Value::BoolVector(v) => v.len(),
+            Value::StringVector(v) => v.len(),
+            Value::Masked { values, .. } => values.len(),
+            Value::Record(fields) => fields.values().map(Value::len).max().unwrap_or(0),
+            Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::String(_) | Value::Null => 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Broadcasts a scalar value (produced by folding a literal) out to `len` repeated
+    /// lanes, so it can take part in an element-wise op alongside a real column. A value
+    /// that's already a column of the right length is returned unchanged. `Null` has no
+    /// vector form of its own - it broadcasts to itself, and is only ever resolved (by
+    /// `select`, into a `Masked` column) rather than expanded into a column of nulls.
+    fn broadcast(self, len: usize) -> Value {
+        match self {
+            Value::Int(n) => Value::IntVector(vec![n; len]),
+            Value::Float(n) => Value::FloatVector(vec![n; len]),
+            Value::Bool(b) => Value::BoolVector(vec![b; len]),
+            Value::String(s) => Value::StringVector(vec![s; len]),
+            other => other,
+        }
+    }
+}
+
+/// Errors raised while evaluating a vectorized, or scalar-fallback, function body.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum EvalError {
+    #[error("unbound identifier `{0}`")]
+    UnboundIdentifier(String),
+    #[error("no such field `{0}`")]
+    NoSuchField(String),
+    #[error("unsupported node in evaluation: {0}")]
+    Unsupported(String),
+    #[error("type mismatch evaluating `{0}`")]
+    TypeMismatch(String),
+    #[error("division by zero")]
+    DivideByZero,
+    #[error("arithmetic overflow evaluating `{0}`")]
+    ArithmeticOverflow(String),
 }
 
+/// Performs the actual elementwise math for a vectorized body. `Machine` drives evaluation
+/// order (walking the `let`s and the returned record); `Runtime` is where the per-kernel
+/// column operations - and, eventually, a faster (e.g. SIMD) backend - live.
 pub trait Runtime {
-    fn print(&self, value: &Value) {
-        match value {
-            Value::Int(n) => println!("{}", n),
-            Value::Float(n) => println!("{}", n),
+    /// Evaluates a binary operator over two same-length column (or scalar) operands.
+    fn binary(&self, op: &Operator, left: Value, right: Value) -> Result<Value, EvalError>;
+    /// Evaluates a unary operator over one column (or scalar) operand.
+    fn unary(&self, op: &Operator, argument: Value) -> Result<Value, EvalError>;
+    /// Evaluates a logical (`and`/`or`) operator over two boolean column operands.
+    fn logical(&self, op: &Operator, left: Value, right: Value) -> Result<Value, EvalError>;
+    /// Lane-wise select: `cond[i] ? then[i] : else[i]`.
+    fn select(&self, cond: Value, then: Value, els: Value) -> Result<Value, EvalError>;
+}
+
+/// The reference [`Runtime`]: plain `Vec<T>` columns, no SIMD. Used in tests, and as the
+/// default until a faster backend is plugged in.
+#[derive(Default)]
+pub struct ColumnRuntime;
+
+fn zip_vec<T: Clone, R>(
+    op_name: &str,
+    left: Vec<T>,
+    right: Vec<T>,
+    f: impl Fn(T, T) -> Result<R, EvalError>,
+) -> Result<Vec<R>, EvalError> {
+    if left.len() != right.len() {
+        return Err(EvalError::TypeMismatch(format!(
+            "`{}`: column length mismatch ({} vs {})",
+            op_name,
+            left.len(),
+            right.len()
+        )));
+    }
+    left.into_iter().zip(right).map(|(a, b)| f(a, b)).collect()
+}
+
+impl Runtime for ColumnRuntime {
+    fn binary(&self, op: &Operator, left: Value, right: Value) -> Result<Value, EvalError> {
+        // Row-at-a-time evaluation (the scalar fallback in `Machine::run_scalar_fallback`)
+        // calls through `Runtime` with bare scalars rather than single-element columns;
+        // handle that shape directly instead of forcing it through the columnar path below.
+        match (left, right) {
+            (Value::Int(a), Value::Int(b)) => Ok(Value::Int(int_op(op, a, b)?)),
+            (Value::Float(a), Value::Float(b)) => Ok(Value::Float(float_op(op, a, b)?)),
+            (Value::String(a), Value::String(b)) if *op == Operator::AdditionOperator => {
+                Ok(Value::String(a + &b))
+            }
+            (left, right) => self.binary_columns(op, left, right),
+        }
+    }
+
+    fn unary(&self, op: &Operator, argument: Value) -> Result<Value, EvalError> {
+        match argument {
+            Value::Int(n) if *op == Operator::SubtractionOperator => Ok(Value::Int(-n)),
+            Value::Float(n) if *op == Operator::SubtractionOperator => Ok(Value::Float(-n)),
+            Value::Bool(b) if *op == Operator::NotOperator => Ok(Value::Bool(!b)),
+            argument => self.unary_columns(op, argument),
+        }
+    }
+
+    fn logical(&self, op: &Operator, left: Value, right: Value) -> Result<Value, EvalError> {
+        match (left, right) {
+            (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(match op {
+                Operator::AndOperator => a && b,
+                Operator::OrOperator => a || b,
+                _ => return Err(EvalError::Unsupported(format!("logical `{}`", op))),
+            })),
+            (left, right) => self.logical_columns(op, left, right),
+        }
+    }
+
+    fn select(&self, cond: Value, then: Value, els: Value) -> Result<Value, EvalError> {
+        if let Value::Bool(c) = cond {
+            // No masking needed for a single row: whichever branch `c` picks - including a
+            // `Value::Null` branch - is exactly the result, same as the non-vectorized
+            // (row-at-a-time) interpreter would produce.
+            return Ok(if c { then } else { els });
+        }
+        self.select_columns(cond, then, els)
+    }
+}
+
+impl ColumnRuntime {
+    fn binary_columns(&self, op: &Operator, left: Value, right: Value) -> Result<Value, EvalError> {
+        let len = left.len().max(right.len());
+        match (left.broadcast(len), right.broadcast(len)) {
+            (Value::IntVector(l), Value::IntVector(r)) => {
+                Ok(Value::IntVector(zip_vec("binary", l, r, |a, b| {
+                    int_op(op, a, b)
+                })?))
+            }
+            (Value::FloatVector(l), Value::FloatVector(r)) => {
+                Ok(Value::FloatVector(zip_vec("binary", l, r, |a, b| {
+                    float_op(op, a, b)
+                })?))
+            }
+            (Value::StringVector(l), Value::StringVector(r))
+                if *op == Operator::AdditionOperator =>
+            {
+                Ok(Value::StringVector(zip_vec("binary", l, r, |a, b| {
+                    Ok(a + &b)
+                })?))
+            }
+            _ => Err(EvalError::TypeMismatch(format!("binary `{}`", op))),
+        }
+    }
+
+    fn unary_columns(&self, op: &Operator, argument: Value) -> Result<Value, EvalError> {
+        match argument {
+            Value::IntVector(v) if *op == Operator::SubtractionOperator => {
+                Ok(Value::IntVector(v.into_iter().map(|n| -n).collect()))
+            }
+            Value::FloatVector(v) if *op == Operator::SubtractionOperator => {
+                Ok(Value::FloatVector(v.into_iter().map(|n| -n).collect()))
+            }
+            Value::BoolVector(v) if *op == Operator::NotOperator => {
+                Ok(Value::BoolVector(v.into_iter().map(|b| !b).collect()))
+            }
+            _ => Err(EvalError::TypeMismatch(format!("unary `{}`", op))),
+        }
+    }
+
+    fn logical_columns(&self, op: &Operator, left: Value, right: Value) -> Result<Value, EvalError> {
+        let len = left.len().max(right.len());
+        match (left.broadcast(len), right.broadcast(len)) {
+            (Value::BoolVector(l), Value::BoolVector(r)) => {
+                Ok(Value::BoolVector(zip_vec("logical", l, r, |a, b| {
+                    Ok(match op {
+                        Operator::AndOperator => a && b,
+                        Operator::OrOperator => a || b,
+                        _ => return Err(EvalError::Unsupported(format!("logical `{}`", op))),
+                    })
+                })?))
+            }
+            _ => Err(EvalError::TypeMismatch(format!("logical `{}`", op))),
+        }
+    }
+
+    fn select_columns(&self, cond: Value, then: Value, els: Value) -> Result<Value, EvalError> {
+        let len = cond.len().max(then.len()).max(els.len());
+        let cond = match cond.broadcast(len) {
+            Value::BoolVector(v) => v,
+            _ => return Err(EvalError::TypeMismatch("select condition".into())),
+        };
+
+        // A `null` branch has no real value of its own: the lanes that would have selected
+        // it come back masked-invalid instead, and the other (real) branch's values fill
+        // every lane of `values` since nothing ever reads through an invalid one.
+        match (then, els) {
+            (Value::Null, Value::Null) => Ok(Value::Masked {
+                values: Box::new(Value::BoolVector(vec![false; len])),
+                valid: vec![false; len],
+            }),
+            (Value::Null, els) => {
+                let els = els.broadcast(len);
+                let valid = cond.iter().map(|c| !c).collect();
+                Ok(Value::Masked {
+                    values: Box::new(els),
+                    valid,
+                })
+            }
+            (then, Value::Null) => {
+                let then = then.broadcast(len);
+                Ok(Value::Masked {
+                    values: Box::new(then),
+                    valid: cond,
+                })
+            }
+            (then, els) => match (then.broadcast(len), els.broadcast(len)) {
+                (Value::IntVector(t), Value::IntVector(e)) => {
+                    Ok(Value::IntVector(lane_select(&cond, t, e)))
+                }
+                (Value::FloatVector(t), Value::FloatVector(e)) => {
+                    Ok(Value::FloatVector(lane_select(&cond, t, e)))
+                }
+                (Value::BoolVector(t), Value::BoolVector(e)) => {
+                    Ok(Value::BoolVector(lane_select(&cond, t, e)))
+                }
+                (Value::StringVector(t), Value::StringVector(e)) => {
+                    Ok(Value::StringVector(lane_select(&cond, t, e)))
+                }
+                _ => Err(EvalError::TypeMismatch("select branches".into())),
+            },
+        }
+    }
+}
+
+fn lane_select<T>(cond: &[bool], then: Vec<T>, els: Vec<T>) -> Vec<T> {
+    then.into_iter()
+        .zip(els)
+        .zip(cond)
+        .map(|((t, e), c)| if *c { t } else { e })
+        .collect()
+}
+
+fn int_op(op: &Operator, a: i64, b: i64) -> Result<i64, EvalError> {
+    use Operator::*;
+    // A runtime kernel must not panic on otherwise-valid input data, so overflow and
+    // division/modulo by zero are reported as `EvalError`s instead of using `+`/`/`/`%`
+    // directly (which panic on overflow in debug builds, and `/`/`%` always panic on a
+    // zero divisor regardless of build profile).
+    match op {
+        AdditionOperator => a
+            .checked_add(b)
+            .ok_or_else(|| EvalError::ArithmeticOverflow(format!("{} + {}", a, b))),
+        SubtractionOperator => a
+            .checked_sub(b)
+            .ok_or_else(|| EvalError::ArithmeticOverflow(format!("{} - {}", a, b))),
+        MultiplicationOperator => a
+            .checked_mul(b)
+            .ok_or_else(|| EvalError::ArithmeticOverflow(format!("{} * {}", a, b))),
+        DivisionOperator if b == 0 => Err(EvalError::DivideByZero),
+        DivisionOperator => a
+            .checked_div(b)
+            .ok_or_else(|| EvalError::ArithmeticOverflow(format!("{} / {}", a, b))),
+        ModuloOperator if b == 0 => Err(EvalError::DivideByZero),
+        ModuloOperator => a
+            .checked_rem(b)
+            .ok_or_else(|| EvalError::ArithmeticOverflow(format!("{} % {}", a, b))),
+        _ => Err(EvalError::Unsupported(format!("int `{}`", op))),
+    }
+}
+
+fn float_op(op: &Operator, a: f64, b: f64) -> Result<f64, EvalError> {
+    use Operator::*;
+    Ok(match op {
+        AdditionOperator => a + b,
+        SubtractionOperator => a - b,
+        MultiplicationOperator => a * b,
+        DivisionOperator => a / b,
+        ModuloOperator => a % b,
+        _ => return Err(EvalError::Unsupported(format!("float `{}`", op))),
+    })
+}
+
+/// Executes a `FunctionExpr` over a batch of named input columns, preferring its
+/// vectorized body and falling back to a row-at-a-time scalar evaluation when the
+/// vectorizer couldn't lower the function at all.
+pub struct Machine<T> {
+    runtime: T,
+}
+
+impl<T: Runtime> Machine<T> {
+    pub fn new(runtime: T) -> Box<Machine<T>> {
+        Box::new(Machine { runtime })
+    }
+
+    /// Runs `function` against `batch` (a record of named input columns) and returns the
+    /// output record.
+    pub fn run(
+        &self,
+        function: &FunctionExpr,
+        batch: HashMap<String, Value>,
+    ) -> Result<Value, EvalError> {
+        match &function.vectorized {
+            Some(vectorized) => self.eval_block(&vectorized.body, &batch),
+            None => self.run_scalar_fallback(function, batch),
+        }
+    }
+
+    fn eval_block(&self, mut block: &Block, env: &HashMap<String, Value>) -> Result<Value, EvalError> {
+        let mut env = env.clone();
+        loop {
+            match block {
+                Block::Variable(assign, next) => {
+                    let value = self.eval(&assign.init, &env)?;
+                    env.insert(assign.id.name.to_string(), value);
+                    block = next;
+                }
+                Block::Return(stmt) => return self.eval(&stmt.argument, &env),
+                Block::Expr(_, next) => block = next,
+            }
+        }
+    }
+
+    fn eval(&self, expr: &Expression, env: &HashMap<String, Value>) -> Result<Value, EvalError> {
+        match expr {
+            Expression::Identifier(id) => env
+                .get(&id.name.to_string())
+                .cloned()
+                .ok_or_else(|| EvalError::UnboundIdentifier(id.name.to_string())),
+            Expression::Member(m) => {
+                let object = self.eval(&m.object, env)?;
+                match object {
+                    Value::Record(mut fields) => fields
+                        .remove(&m.property)
+                        .ok_or_else(|| EvalError::NoSuchField(m.property.clone())),
+                    _ => Err(EvalError::TypeMismatch("member access on non-record".into())),
+                }
+            }
+            Expression::Integer(lit) => Ok(Value::Int(lit.value)),
+            Expression::Float(lit) => Ok(Value::Float(lit.value)),
+            Expression::Boolean(lit) => Ok(Value::Bool(lit.value)),
+            Expression::StringLit(lit) => Ok(Value::String(lit.value.clone())),
+            Expression::Binary(e) => {
+                let left = self.eval(&e.left, env)?;
+                let right = self.eval(&e.right, env)?;
+                self.runtime.binary(&e.operator, left, right)
+            }
+            Expression::Unary(e) => {
+                let argument = self.eval(&e.argument, env)?;
+                self.runtime.unary(&e.operator, argument)
+            }
+            Expression::Logical(e) => {
+                let left = self.eval(&e.left, env)?;
+                let right = self.eval(&e.right, env)?;
+                self.runtime.logical(&e.operator, left, right)
+            }
+            Expression::Conditional(e) => {
+                let test = self.eval(&e.test, env)?;
+                let consequent = self.eval(&e.consequent, env)?;
+                let alternate = self.eval(&e.alternate, env)?;
+                self.runtime.select(test, consequent, alternate)
+            }
+            Expression::Object(e) => {
+                let mut fields = match &e.with {
+                    Some(with) => match env.get(&with.name.to_string()) {
+                        Some(Value::Record(fields)) => fields.clone(),
+                        _ => HashMap::new(),
+                    },
+                    None => HashMap::new(),
+                };
+                for property in &e.properties {
+                    let value = self.eval(&property.value, env)?;
+                    fields.insert(property.key.name.to_string(), value);
+                }
+                Ok(Value::Record(fields))
+            }
+            _ => Err(EvalError::Unsupported(format!(
+                "node not produced by the vectorizer: {:?}",
+                expr.loc()
+            ))),
+        }
+    }
+
+    /// Evaluates `function`'s original (un-vectorized) body one row at a time, for any
+    /// function the vectorizer left entirely un-lowered.
+    fn run_scalar_fallback(
+        &self,
+        function: &FunctionExpr,
+        batch: HashMap<String, Value>,
+    ) -> Result<Value, EvalError> {
+        let len = batch.values().map(Value::len).max().unwrap_or(0);
+        let mut rows: Vec<HashMap<String, Value>> = Vec::with_capacity(len);
+        for i in 0..len {
+            let mut row = HashMap::new();
+            for (name, column) in &batch {
+                row.insert(name.clone(), row_value(column, i)?);
+            }
+            rows.push(self.eval_block(&function.body, &row)?);
+        }
+        Ok(columns_of_rows(rows))
+    }
+}
+
+fn row_value(column: &Value, i: usize) -> Result<Value, EvalError> {
+    Ok(match column {
+        Value::IntVector(v) => Value::Int(v[i]),
+        Value::FloatVector(v) => Value::Float(v[i]),
+        Value::BoolVector(v) => Value::Bool(v[i]),
+        Value::StringVector(v) => Value::String(v[i].clone()),
+        Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::String(_) | Value::Null => {
+            column.clone()
+        }
+        Value::Masked { values, valid } => {
+            if valid[i] {
+                row_value(values, i)?
+            } else {
+                Value::Null
+            }
+        }
+        // A batch's `r` column is itself a `Record` of columns (e.g. `{x: IntVector, y:
+        // IntVector}`); pulling row `i` out of it means pulling row `i` out of every field.
+        Value::Record(fields) => Value::Record(
+            fields
+                .iter()
+                .map(|(name, column)| Ok((name.clone(), row_value(column, i)?)))
+                .collect::<Result<HashMap<_, _>, EvalError>>()?,
+        ),
+    })
+}
+
+/// Regroups a `Vec` of per-row values back into a single columnar `Value`, the inverse of
+/// iterating a batch row-by-row in `run_scalar_fallback`.
+///
+/// A function like `(r) => ({r with z: r.x + r.y})` produces one `Value::Record` per row,
+/// which gets regrouped into a `Value::Record` of columns below - but an entirely ordinary
+/// function like `(r) => r.x + r.y` produces a bare scalar per row instead, and needs its rows
+/// stacked directly into a single column rather than silently dropped.
+fn columns_of_rows(rows: Vec<Value>) -> Value {
+    if rows.iter().any(|row| !matches!(row, Value::Record(_))) {
+        return stack(rows);
+    }
+
+    let mut columns: HashMap<String, Vec<Value>> = HashMap::new();
+    for row in rows {
+        if let Value::Record(fields) = row {
+            for (name, value) in fields {
+                columns.entry(name).or_default().push(value);
+            }
+        }
+    }
+    Value::Record(
+        columns
+            .into_iter()
+            .map(|(name, values)| (name, stack(values)))
+            .collect(),
+    )
+}
+
+fn stack(values: Vec<Value>) -> Value {
+    if values.iter().all(|v| matches!(v, Value::Int(_))) {
+        Value::IntVector(
+            values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Int(n) => n,
+                    _ => unreachable!(),
+                })
+                .collect(),
+        )
+    } else if values.iter().all(|v| matches!(v, Value::Float(_))) {
+        Value::FloatVector(
+            values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Float(n) => n,
+                    _ => unreachable!(),
+                })
+                .collect(),
+        )
+    } else if values.iter().all(|v| matches!(v, Value::Bool(_))) {
+        Value::BoolVector(
+            values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Bool(b) => b,
+                    _ => unreachable!(),
+                })
+                .collect(),
+        )
+    } else {
+        Value::StringVector(
+            values
+                .into_iter()
+                .map(|v| match v {
+                    Value::String(s) => s,
+                    _ => String::new(),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluxcore::{
+        ast,
+        semantic::{
+            nodes::{
+                BinaryExpr, FunctionParameter, Identifier, IdentifierExpr, MemberExpr, ObjectExpr,
+                Property, ReturnStmt,
+            },
+            types::{Function, MonoType, Property as TypeProperty, Record},
+            Symbol,
+        },
+    };
+
+    fn loc() -> ast::SourceLocation {
+        Default::default()
+    }
+
+    fn member(object: &str, property: &str) -> Expression {
+        Expression::Member(Box::new(MemberExpr {
+            loc: loc(),
+            typ: MonoType::vector(MonoType::INT),
+            object: Expression::Identifier(Box::new(IdentifierExpr {
+                loc: loc(),
+                typ: MonoType::from(Record::new(
+                    vec![
+                        TypeProperty {
+                            k: "x".into(),
+                            v: MonoType::vector(MonoType::INT),
+                        },
+                        TypeProperty {
+                            k: "y".into(),
+                            v: MonoType::vector(MonoType::INT),
+                        },
+                    ],
+                    None,
+                )),
+                name: Symbol::from(object),
+            })),
+            property: property.to_string(),
+        }))
+    }
+
+    // `(r) => ({r with z: r.x + r.y})`, in a hand-built vectorized form (the real vectorizer
+    // would also bind `r.x + r.y` to a synthetic `let` ahead of the return).
+    fn with_z_is_x_plus_y() -> FunctionExpr {
+        let record_type = MonoType::from(Record::new(
+            vec![
+                TypeProperty {
+                    k: "x".into(),
+                    v: MonoType::vector(MonoType::INT),
+                },
+                TypeProperty {
+                    k: "y".into(),
+                    v: MonoType::vector(MonoType::INT),
+                },
+            ],
+            None,
+        ));
+
+        let add = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            typ: MonoType::vector(MonoType::INT),
+            operator: Operator::AdditionOperator,
+            left: member("r", "x"),
+            right: member("r", "y"),
+        }));
+
+        let body = Block::Return(ReturnStmt {
+            loc: loc(),
+            argument: Expression::Object(Box::new(ObjectExpr {
+                loc: loc(),
+                typ: record_type.clone(),
+                with: Some(IdentifierExpr {
+                    loc: loc(),
+                    typ: record_type.clone(),
+                    name: Symbol::from("r"),
+                }),
+                properties: vec![Property {
+                    loc: loc(),
+                    key: Identifier {
+                        loc: loc(),
+                        name: Symbol::from("z"),
+                    },
+                    value: add,
+                }],
+            })),
+        });
+
+        FunctionExpr {
+            loc: loc(),
+            typ: MonoType::from(Function {
+                pipe: None,
+                req: [("r".to_string(), record_type)].into_iter().collect(),
+                opt: Default::default(),
+                retn: MonoType::INT,
+            }),
+            params: vec![FunctionParameter {
+                loc: loc(),
+                key: Identifier {
+                    loc: loc(),
+                    name: Symbol::from("r"),
+                },
+                default: None,
+                is_pipe: false,
+            }],
+            body: body.clone(),
+            vectorized: Some(Box::new(FunctionExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                params: vec![],
+                body,
+                vectorized: None,
+            })),
+        }
+    }
+
+    #[test]
+    fn evaluates_vectorized_add_over_a_batch() {
+        let function = with_z_is_x_plus_y();
+        let machine = Machine::new(ColumnRuntime);
+
+        let mut r = HashMap::new();
+        r.insert("x".to_string(), Value::IntVector(vec![1, 2, 3]));
+        r.insert("y".to_string(), Value::IntVector(vec![10, 20, 30]));
+        let mut batch = HashMap::new();
+        batch.insert("r".to_string(), Value::Record(r));
+
+        let result = machine.run(&function, batch).unwrap();
+        match result {
+            Value::Record(fields) => {
+                assert_eq!(fields.get("z"), Some(&Value::IntVector(vec![11, 22, 33])));
+            }
+            other => panic!("expected a record, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn select_masks_the_lanes_that_choose_the_null_branch() {
+        let runtime = ColumnRuntime;
+        let cond = Value::BoolVector(vec![true, false, true]);
+        let then = Value::IntVector(vec![1, 2, 3]);
+
+        let result = runtime.select(cond, then, Value::Null).unwrap();
+        assert_eq!(
+            result,
+            Value::Masked {
+                values: Box::new(Value::IntVector(vec![1, 2, 3])),
+                valid: vec![true, false, true],
+            }
+        );
+    }
+
+    #[test]
+    fn select_masks_the_lanes_that_choose_the_other_null_branch() {
+        let runtime = ColumnRuntime;
+        let cond = Value::BoolVector(vec![true, false, true]);
+        let els = Value::IntVector(vec![1, 2, 3]);
+
+        let result = runtime.select(cond, Value::Null, els).unwrap();
+        assert_eq!(
+            result,
+            Value::Masked {
+                values: Box::new(Value::IntVector(vec![1, 2, 3])),
+                valid: vec![false, true, false],
+            }
+        );
+    }
+
+    #[test]
+    fn int_division_by_zero_does_not_panic() {
+        assert_eq!(
+            int_op(&Operator::DivisionOperator, 1, 0),
+            Err(EvalError::DivideByZero)
+        );
+        assert_eq!(
+            int_op(&Operator::ModuloOperator, 1, 0),
+            Err(EvalError::DivideByZero)
+        );
+    }
+
+    #[test]
+    fn int_overflow_does_not_panic() {
+        assert_eq!(
+            int_op(&Operator::AdditionOperator, i64::MAX, 1),
+            Err(EvalError::ArithmeticOverflow(format!("{} + {}", i64::MAX, 1)))
+        );
+    }
+
+    // `(r) => ({z: r.x + r.y})`, left un-vectorized (`vectorized: None`) so
+    // `Machine::run` takes the row-at-a-time fallback instead of `eval_block`.
+    fn with_z_is_x_plus_y_unvectorized() -> FunctionExpr {
+        let record_type = MonoType::from(Record::new(
+            vec![
+                TypeProperty {
+                    k: "x".into(),
+                    v: MonoType::INT,
+                },
+                TypeProperty {
+                    k: "y".into(),
+                    v: MonoType::INT,
+                },
+            ],
+            None,
+        ));
+
+        let add = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            typ: MonoType::INT,
+            operator: Operator::AdditionOperator,
+            left: Expression::Member(Box::new(MemberExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                object: Expression::Identifier(Box::new(IdentifierExpr {
+                    loc: loc(),
+                    typ: record_type.clone(),
+                    name: Symbol::from("r"),
+                })),
+                property: "x".to_string(),
+            })),
+            right: Expression::Member(Box::new(MemberExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                object: Expression::Identifier(Box::new(IdentifierExpr {
+                    loc: loc(),
+                    typ: record_type.clone(),
+                    name: Symbol::from("r"),
+                })),
+                property: "y".to_string(),
+            })),
+        }));
+
+        let body = Block::Return(ReturnStmt {
+            loc: loc(),
+            argument: Expression::Object(Box::new(ObjectExpr {
+                loc: loc(),
+                typ: record_type.clone(),
+                with: None,
+                properties: vec![Property {
+                    loc: loc(),
+                    key: Identifier {
+                        loc: loc(),
+                        name: Symbol::from("z"),
+                    },
+                    value: add,
+                }],
+            })),
+        });
+
+        FunctionExpr {
+            loc: loc(),
+            typ: MonoType::from(Function {
+                pipe: None,
+                req: [("r".to_string(), record_type)].into_iter().collect(),
+                opt: Default::default(),
+                retn: MonoType::INT,
+            }),
+            params: vec![FunctionParameter {
+                loc: loc(),
+                key: Identifier {
+                    loc: loc(),
+                    name: Symbol::from("r"),
+                },
+                default: None,
+                is_pipe: false,
+            }],
+            body,
+            vectorized: None,
+        }
+    }
+
+    #[test]
+    fn scalar_fallback_decomposes_record_columns_row_by_row() {
+        let function = with_z_is_x_plus_y_unvectorized();
+        let machine = Machine::new(ColumnRuntime);
+
+        let mut r = HashMap::new();
+        r.insert("x".to_string(), Value::IntVector(vec![1, 2, 3]));
+        r.insert("y".to_string(), Value::IntVector(vec![10, 20, 30]));
+        let mut batch = HashMap::new();
+        batch.insert("r".to_string(), Value::Record(r));
+
+        let result = machine.run(&function, batch).unwrap();
+        match result {
+            Value::Record(fields) => {
+                assert_eq!(fields.get("z"), Some(&Value::IntVector(vec![11, 22, 33])));
+            }
+            other => panic!("expected a record, got {:?}", other),
+        }
+    }
+
+    // `(r) => r.x + r.y`, left un-vectorized - an entirely ordinary function whose body is a
+    // bare expression rather than an object literal, unlike `with_z_is_x_plus_y_unvectorized`.
+    fn with_x_plus_y_unvectorized() -> FunctionExpr {
+        let record_type = MonoType::from(Record::new(
+            vec![
+                TypeProperty {
+                    k: "x".into(),
+                    v: MonoType::INT,
+                },
+                TypeProperty {
+                    k: "y".into(),
+                    v: MonoType::INT,
+                },
+            ],
+            None,
+        ));
+
+        let add = Expression::Binary(Box::new(BinaryExpr {
+            loc: loc(),
+            typ: MonoType::INT,
+            operator: Operator::AdditionOperator,
+            left: Expression::Member(Box::new(MemberExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                object: Expression::Identifier(Box::new(IdentifierExpr {
+                    loc: loc(),
+                    typ: record_type.clone(),
+                    name: Symbol::from("r"),
+                })),
+                property: "x".to_string(),
+            })),
+            right: Expression::Member(Box::new(MemberExpr {
+                loc: loc(),
+                typ: MonoType::INT,
+                object: Expression::Identifier(Box::new(IdentifierExpr {
+                    loc: loc(),
+                    typ: record_type.clone(),
+                    name: Symbol::from("r"),
+                })),
+                property: "y".to_string(),
+            })),
+        }));
+
+        FunctionExpr {
+            loc: loc(),
+            typ: MonoType::from(Function {
+                pipe: None,
+                req: [("r".to_string(), record_type)].into_iter().collect(),
+                opt: Default::default(),
+                retn: MonoType::INT,
+            }),
+            params: vec![FunctionParameter {
+                loc: loc(),
+                key: Identifier {
+                    loc: loc(),
+                    name: Symbol::from("r"),
+                },
+                default: None,
+                is_pipe: false,
+            }],
+            body: Block::Return(ReturnStmt {
+                loc: loc(),
+                argument: add,
+            }),
+            vectorized: None,
         }
     }
+
+    #[test]
+    fn scalar_fallback_stacks_a_bare_non_record_result_instead_of_dropping_every_row() {
+        let function = with_x_plus_y_unvectorized();
+        let machine = Machine::new(ColumnRuntime);
+
+        let mut r = HashMap::new();
+        r.insert("x".to_string(), Value::IntVector(vec![1, 2, 3]));
+        r.insert("y".to_string(), Value::IntVector(vec![10, 20, 30]));
+        let mut batch = HashMap::new();
+        batch.insert("r".to_string(), Value::Record(r));
+
+        let result = machine.run(&function, batch).unwrap();
+        assert_eq!(
+            result,
+            Value::IntVector(vec![11, 22, 33]),
+            "a function returning a bare scalar per row should stack those rows into a \
+             column, not silently discard every row because it isn't a Value::Record",
+        );
+    }
 }